@@ -0,0 +1,26 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Detects the compiler version so we can use `core::error::Error` when it is available.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(rust_v_1_81)");
+    println!("cargo:rustc-check-cfg=cfg(bench)");
+
+    // If the version can not be detected we fall back to not using `core::error::Error`.
+    if rustc_minor_version().map_or(false, |minor| minor >= 81) {
+        println!("cargo:rustc-cfg=rust_v_1_81");
+    }
+}
+
+/// Returns the minor version of the compiler, `None` if it can not be determined.
+fn rustc_minor_version() -> Option<u32> {
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+    let version = String::from_utf8(output.stdout).ok()?;
+
+    // Version string is of the form "rustc 1.81.0 (eeb90cda1 2024-09-04)".
+    version.split('.').nth(1)?.parse().ok()
+}
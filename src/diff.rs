@@ -0,0 +1,546 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Previewing, and reporting, the changes that combining two PSBTs makes.
+//!
+//! See [`Psbt::diff`] and [`Psbt::combine_with_report`].
+
+use bitcoin::bip32::Xpub;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::taproot::TapLeafHash;
+use bitcoin::PublicKey;
+
+use crate::internal_prelude::Vec;
+use crate::{CombineError, Input, Output, Psbt};
+
+/// Pushes the field of each optional field set in `after` but not in `before`, and of each map
+/// field with keys in `after` that are not in `before`, onto `filled`.
+macro_rules! filled {
+    (
+        $before:ident,
+        $after:ident,
+        $filled:ident,
+        options: { $($option:ident => $option_field:expr),* },
+        maps: { $($map:ident => $map_field:expr),* }
+    ) => {
+        $(
+            if $before.$option.is_none() && $after.$option.is_some() {
+                $filled.push($option_field);
+            }
+        )*
+        $(
+            if $after.$map.keys().any(|key| !$before.$map.contains_key(key)) {
+                $filled.push($map_field);
+            }
+        )*
+    };
+}
+
+/// The changes that combining `other` into a PSBT would make, as returned by [`Psbt::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PsbtDiff {
+    /// The error combining would return, if the PSBTs conflict.
+    ///
+    /// If set the combine would fail and none of the other changes would be made.
+    pub conflict: Option<CombineError>,
+    /// The xpubs in `other` that are not in this PSBT.
+    pub xpubs_added: Vec<Xpub>,
+    /// The changes to each input, inputs that would not change are not included.
+    pub inputs: Vec<InputDiff>,
+    /// The changes to each output, outputs that would not change are not included.
+    pub outputs: Vec<OutputDiff>,
+}
+
+impl PsbtDiff {
+    /// Returns true if combining would neither change nor conflict with this PSBT.
+    pub fn is_empty(&self) -> bool {
+        self.conflict.is_none()
+            && self.xpubs_added.is_empty()
+            && self.inputs.is_empty()
+            && self.outputs.is_empty()
+    }
+
+    /// Returns the total number of signatures that would be added across all inputs.
+    pub fn signatures_added(&self) -> usize {
+        self.inputs.iter().map(|input| input.signatures_added()).sum()
+    }
+}
+
+/// The changes that combining would make to a single input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputDiff {
+    /// The index of the input.
+    pub index: usize,
+    /// The keys of the ECDSA signatures that would be added to `partial_sigs`.
+    pub partial_sigs_added: Vec<PublicKey>,
+    /// True if a taproot key path signature would be added.
+    pub tap_key_sig_added: bool,
+    /// The keys and leaf hashes of the taproot script path signatures that would be added.
+    pub tap_script_sigs_added: Vec<(XOnlyPublicKey, TapLeafHash)>,
+    /// True if a funding UTXO (`witness_utxo` or `non_witness_utxo`) would be added.
+    pub utxo_added: bool,
+    /// True if the input would become finalized (see [`Input::is_finalized`]).
+    pub finalized: bool,
+}
+
+impl InputDiff {
+    /// Returns the changes combining `that` into `this` would make, or `None` if there are none.
+    ///
+    /// Signatures and final scripts that already exist in `this` are not reported as added since
+    /// combining never overwrites them. If the combined input would be finalized no signatures
+    /// are reported as added since combining clears them (see [`Input::combine`]).
+    pub(crate) fn new(index: usize, this: &Input, that: &Input) -> Option<Self> {
+        let this_finalized = this.is_finalized();
+        let finalized_after = this_finalized
+            || ((this.final_script_sig.is_some() || that.final_script_sig.is_some())
+                && (this.final_script_witness.is_some() || that.final_script_witness.is_some()));
+
+        let (partial_sigs_added, tap_key_sig_added, tap_script_sigs_added) = if finalized_after {
+            (Vec::new(), false, Vec::new())
+        } else {
+            let partial_sigs_added: Vec<PublicKey> = that
+                .partial_sigs
+                .keys()
+                .filter(|pk| !this.partial_sigs.contains_key(pk))
+                .copied()
+                .collect();
+            let tap_key_sig_added = this.tap_key_sig.is_none() && that.tap_key_sig.is_some();
+            let tap_script_sigs_added: Vec<(XOnlyPublicKey, TapLeafHash)> = that
+                .tap_script_sigs
+                .keys()
+                .filter(|key| !this.tap_script_sigs.contains_key(key))
+                .copied()
+                .collect();
+            (partial_sigs_added, tap_key_sig_added, tap_script_sigs_added)
+        };
+        let utxo_added = (this.witness_utxo.is_none() && that.witness_utxo.is_some())
+            || (this.non_witness_utxo.is_none() && that.non_witness_utxo.is_some());
+        let finalized = !this_finalized && finalized_after;
+
+        let diff = InputDiff {
+            index,
+            partial_sigs_added,
+            tap_key_sig_added,
+            tap_script_sigs_added,
+            utxo_added,
+            finalized,
+        };
+        if diff.is_empty() {
+            None
+        } else {
+            Some(diff)
+        }
+    }
+
+    /// Returns true if combining would not change this input.
+    pub fn is_empty(&self) -> bool {
+        self.partial_sigs_added.is_empty()
+            && !self.tap_key_sig_added
+            && self.tap_script_sigs_added.is_empty()
+            && !self.utxo_added
+            && !self.finalized
+    }
+
+    /// Returns the number of signatures that would be added to this input.
+    pub fn signatures_added(&self) -> usize {
+        self.partial_sigs_added.len()
+            + usize::from(self.tap_key_sig_added)
+            + self.tap_script_sigs_added.len()
+    }
+}
+
+/// The changes that combining would make to a single output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputDiff {
+    /// The index of the output.
+    pub index: usize,
+    /// The fields that would be filled from `other`, see [`OutputField`].
+    pub fields_added: Vec<OutputField>,
+}
+
+impl OutputDiff {
+    /// Returns the changes combining `that` into `this` would make, or `None` if there are none.
+    pub(crate) fn new(index: usize, this: &Output, that: &Output) -> Option<Self> {
+        let fields_added = output_fields_filled(this, that);
+        if fields_added.is_empty() {
+            None
+        } else {
+            Some(OutputDiff { index, fields_added })
+        }
+    }
+}
+
+impl Psbt {
+    /// Returns the changes that [`Psbt::combine_with`] would make when combining `other`.
+    ///
+    /// Neither PSBT is mutated. Use this to preview the effect of combining an untrusted PSBT,
+    /// e.g., to show that a peer is adding two signatures to input 0 before accepting the merge.
+    pub fn diff(&self, other: &Psbt) -> PsbtDiff {
+        let conflict = self.clone().combine(other.clone()).err();
+
+        let xpubs_added =
+            other.xpub.keys().filter(|xpub| !self.xpub.contains_key(xpub)).copied().collect();
+
+        let inputs = self
+            .inputs
+            .iter()
+            .zip(other.inputs.iter())
+            .enumerate()
+            .filter_map(|(index, (this, that))| InputDiff::new(index, this, that))
+            .collect();
+        let outputs = self
+            .outputs
+            .iter()
+            .zip(other.outputs.iter())
+            .enumerate()
+            .filter_map(|(index, (this, that))| OutputDiff::new(index, this, that))
+            .collect();
+
+        PsbtDiff { conflict, xpubs_added, inputs, outputs }
+    }
+
+    /// Combines this [`Psbt`] with `other`, same as [`Psbt::combine_with`], and reports the
+    /// changes that were actually made.
+    ///
+    /// Unlike [`Psbt::diff`] this is computed from the combined PSBT so it is exactly what
+    /// changed, useful for services that must audit every merge.
+    pub fn combine_with_report(
+        self,
+        other: Self,
+    ) -> Result<(Psbt, CombineReport), CombineError> {
+        let inputs_before = self.inputs.clone();
+        let outputs_before = self.outputs.clone();
+        let combined = self.combine_with(other)?;
+
+        let inputs = inputs_before
+            .iter()
+            .zip(combined.inputs.iter())
+            .enumerate()
+            .filter_map(|(index, (before, after))| InputReport::new(index, before, after))
+            .collect();
+        let outputs = outputs_before
+            .iter()
+            .zip(combined.outputs.iter())
+            .enumerate()
+            .filter_map(|(index, (before, after))| OutputReport::new(index, before, after))
+            .collect();
+
+        Ok((combined, CombineReport { inputs, outputs }))
+    }
+}
+
+/// The changes made by [`Psbt::combine_with_report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CombineReport {
+    /// The changes made to each input, inputs that did not change are not included.
+    pub inputs: Vec<InputReport>,
+    /// The changes made to each output, outputs that did not change are not included.
+    pub outputs: Vec<OutputReport>,
+}
+
+impl CombineReport {
+    /// Returns true if combining did not change any input or output.
+    pub fn is_empty(&self) -> bool { self.inputs.is_empty() && self.outputs.is_empty() }
+
+    /// Returns the total number of signatures added across all inputs.
+    pub fn signatures_added(&self) -> usize {
+        self.inputs.iter().map(|input| input.signatures_added).sum()
+    }
+}
+
+/// The changes combining made to a single input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputReport {
+    /// The index of the input.
+    pub index: usize,
+    /// The fields that were filled from `other`, see [`InputField`].
+    pub fields_filled: Vec<InputField>,
+    /// The number of signatures (ECDSA and taproot) added.
+    pub signatures_added: usize,
+}
+
+impl InputReport {
+    /// Returns the changes between `before` and `after`, or `None` if there are none.
+    fn new(index: usize, before: &Input, after: &Input) -> Option<Self> {
+        let mut fields_filled = Vec::new();
+        filled!(
+            before,
+            after,
+            fields_filled,
+            options: {
+                sequence => InputField::Sequence,
+                min_time => InputField::MinTime,
+                min_height => InputField::MinHeight,
+                non_witness_utxo => InputField::NonWitnessUtxo,
+                witness_utxo => InputField::WitnessUtxo,
+                sighash_type => InputField::SighashType,
+                redeem_script => InputField::RedeemScript,
+                witness_script => InputField::WitnessScript,
+                final_script_sig => InputField::FinalScriptSig,
+                final_script_witness => InputField::FinalScriptWitness,
+                tap_key_sig => InputField::TapKeySig,
+                tap_internal_key => InputField::TapInternalKey,
+                tap_merkle_root => InputField::TapMerkleRoot
+            },
+            maps: {
+                bip32_derivation => InputField::Bip32Derivation,
+                ripemd160_preimages => InputField::Ripemd160Preimages,
+                sha256_preimages => InputField::Sha256Preimages,
+                hash160_preimages => InputField::Hash160Preimages,
+                hash256_preimages => InputField::Hash256Preimages,
+                tap_scripts => InputField::TapScripts,
+                tap_key_origins => InputField::TapKeyOrigins,
+                musig2_participant_pubkeys => InputField::Musig2ParticipantPubkeys,
+                musig2_pub_nonces => InputField::Musig2PubNonces,
+                musig2_partial_sigs => InputField::Musig2PartialSigs,
+                sp_ecdh_share => InputField::SpEcdhShare,
+                sp_dleq_proof => InputField::SpDleqProof,
+                proprietary => InputField::Proprietary,
+                unknown => InputField::Unknown
+            }
+        );
+
+        // Combining a finalized input clears the partial sigs so the count can go down.
+        let signatures_added = signature_count(after).saturating_sub(signature_count(before));
+
+        if fields_filled.is_empty() && signatures_added == 0 {
+            None
+        } else {
+            Some(InputReport { index, fields_filled, signatures_added })
+        }
+    }
+}
+
+/// An input field that combining filled, see [`InputReport::fields_filled`].
+///
+/// An optional field is filled if it was not set before combining, a map field is filled if
+/// entries were added to it. The signature maps `partial_sigs` and `tap_script_sigs` are not
+/// included, they are counted by [`InputReport::signatures_added`]. The outpoint fields are never
+/// changed by combining.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InputField {
+    /// The `sequence` field.
+    Sequence,
+    /// The `min_time` field.
+    MinTime,
+    /// The `min_height` field.
+    MinHeight,
+    /// The `non_witness_utxo` field.
+    NonWitnessUtxo,
+    /// The `witness_utxo` field.
+    WitnessUtxo,
+    /// The `sighash_type` field.
+    SighashType,
+    /// The `redeem_script` field.
+    RedeemScript,
+    /// The `witness_script` field.
+    WitnessScript,
+    /// The `final_script_sig` field.
+    FinalScriptSig,
+    /// The `final_script_witness` field.
+    FinalScriptWitness,
+    /// The `tap_key_sig` field, also counted by [`InputReport::signatures_added`].
+    TapKeySig,
+    /// The `tap_internal_key` field.
+    TapInternalKey,
+    /// The `tap_merkle_root` field.
+    TapMerkleRoot,
+    /// The `bip32_derivation` map.
+    Bip32Derivation,
+    /// The `ripemd160_preimages` map.
+    Ripemd160Preimages,
+    /// The `sha256_preimages` map.
+    Sha256Preimages,
+    /// The `hash160_preimages` map.
+    Hash160Preimages,
+    /// The `hash256_preimages` map.
+    Hash256Preimages,
+    /// The `tap_scripts` map.
+    TapScripts,
+    /// The `tap_key_origins` map.
+    TapKeyOrigins,
+    /// The `musig2_participant_pubkeys` map.
+    Musig2ParticipantPubkeys,
+    /// The `musig2_pub_nonces` map.
+    Musig2PubNonces,
+    /// The `musig2_partial_sigs` map.
+    Musig2PartialSigs,
+    /// The `sp_ecdh_share` map.
+    SpEcdhShare,
+    /// The `sp_dleq_proof` map.
+    SpDleqProof,
+    /// The `proprietary` map.
+    Proprietary,
+    /// The `unknown` map.
+    Unknown,
+}
+
+/// The changes combining made to a single output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputReport {
+    /// The index of the output.
+    pub index: usize,
+    /// The fields that were filled from `other`, see [`OutputField`].
+    pub fields_filled: Vec<OutputField>,
+}
+
+impl OutputReport {
+    /// Returns the changes between `before` and `after`, or `None` if there are none.
+    fn new(index: usize, before: &Output, after: &Output) -> Option<Self> {
+        let fields_filled = output_fields_filled(before, after);
+        if fields_filled.is_empty() {
+            None
+        } else {
+            Some(OutputReport { index, fields_filled })
+        }
+    }
+}
+
+/// Returns the fields of `after` that would be filled into `before` by combining.
+fn output_fields_filled(before: &Output, after: &Output) -> Vec<OutputField> {
+    let mut fields_filled = Vec::new();
+    filled!(
+        before,
+        after,
+        fields_filled,
+        options: {
+            redeem_script => OutputField::RedeemScript,
+            witness_script => OutputField::WitnessScript,
+            tap_internal_key => OutputField::TapInternalKey,
+            tap_tree => OutputField::TapTree
+        },
+        maps: {
+            bip32_derivation => OutputField::Bip32Derivation,
+            tap_key_origins => OutputField::TapKeyOrigins,
+            proprietary => OutputField::Proprietary,
+            unknown => OutputField::Unknown
+        }
+    );
+    fields_filled
+}
+
+/// An output field that combining fills, see [`OutputReport::fields_filled`] and
+/// [`OutputDiff::fields_added`].
+///
+/// An optional field is filled if it was not set before combining, a map field is filled if
+/// entries were added to it. The `amount` and `script_pubkey` fields are never changed by
+/// combining.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OutputField {
+    /// The `redeem_script` field.
+    RedeemScript,
+    /// The `witness_script` field.
+    WitnessScript,
+    /// The `tap_internal_key` field.
+    TapInternalKey,
+    /// The `tap_tree` field.
+    TapTree,
+    /// The `bip32_derivation` map.
+    Bip32Derivation,
+    /// The `tap_key_origins` map.
+    TapKeyOrigins,
+    /// The `proprietary` map.
+    Proprietary,
+    /// The `unknown` map.
+    Unknown,
+}
+
+/// Returns the number of signatures (ECDSA and taproot) in `input`.
+fn signature_count(input: &Input) -> usize {
+    input.partial_sigs.len()
+        + input.tap_script_sigs.len()
+        + usize::from(input.tap_key_sig.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::bip32::{DerivationPath, Fingerprint};
+    use bitcoin::secp256k1::{self, Secp256k1, SecretKey};
+    use bitcoin::sighash::EcdsaSighashType;
+    use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, TxOut, Witness};
+
+    use super::*;
+    use crate::output::test_output;
+    use crate::{Constructor, Modifiable};
+
+    fn pubkey(byte: u8) -> secp256k1::PublicKey {
+        let sk = SecretKey::from_slice(&[byte; 32]).expect("valid secret key");
+        secp256k1::PublicKey::from_secret_key(&Secp256k1::signing_only(), &sk)
+    }
+
+    fn psbt() -> Psbt {
+        let mut input = Input::new(&OutPoint::COINBASE_PREVOUT);
+        let script_pubkey = ScriptBuf::from_bytes(vec![0x51]);
+        input.witness_utxo = Some(TxOut { value: Amount::from_sat(10_000), script_pubkey });
+        let output = test_output(9_000);
+
+        Constructor::<Modifiable>::new()
+            .input(input)
+            .output(output)
+            .into_inner()
+            .expect("valid lock time combination")
+    }
+
+    #[test]
+    fn diff_with_finalized_input_adds_no_signatures() {
+        let sig = {
+            let secp = Secp256k1::signing_only();
+            let sk = SecretKey::from_slice(&[1; 32]).expect("valid secret key");
+            let signature = secp.sign_ecdsa(&secp256k1::Message::from_digest([1; 32]), &sk);
+            bitcoin::ecdsa::Signature { signature, sighash_type: EcdsaSighashType::All }
+        };
+
+        let mut this = psbt();
+        this.inputs[0].final_script_sig = Some(ScriptBuf::new());
+        this.inputs[0].final_script_witness = Some(Witness::new());
+        let mut other = psbt();
+        other.inputs[0].partial_sigs.insert(PublicKey::new(pubkey(1)), sig);
+
+        // The signature is cleared by combining with the finalized input.
+        assert!(this.diff(&other).is_empty());
+
+        let diff = other.diff(&this);
+        assert_eq!(diff.inputs.len(), 1);
+        assert!(diff.inputs[0].partial_sigs_added.is_empty());
+        assert!(diff.inputs[0].finalized);
+    }
+
+    #[test]
+    fn diff_reports_output_fields() {
+        let key_source = (Fingerprint::from([0xd3, 0x4d, 0xb3, 0x3f]), DerivationPath::master());
+
+        let this = psbt();
+        let mut other = psbt();
+        other.outputs[0].bip32_derivation.insert(pubkey(2), key_source);
+
+        let diff = this.diff(&other);
+        assert!(diff.inputs.is_empty());
+        assert_eq!(
+            diff.outputs,
+            [OutputDiff { index: 0, fields_added: vec![OutputField::Bip32Derivation] }]
+        );
+    }
+
+    #[test]
+    fn combine_with_report_fields_filled() {
+        let key_source = (Fingerprint::from([0xd3, 0x4d, 0xb3, 0x3f]), DerivationPath::master());
+
+        let this = psbt();
+        let mut other = psbt();
+        other.inputs[0].sequence = Some(Sequence::ENABLE_RBF_NO_LOCKTIME);
+        other.inputs[0].bip32_derivation.insert(pubkey(1), key_source.clone());
+        other.outputs[0].bip32_derivation.insert(pubkey(2), key_source);
+
+        let (_, report) = this.combine_with_report(other).expect("combine PSBTs");
+
+        assert_eq!(report.inputs.len(), 1);
+        assert_eq!(
+            report.inputs[0].fields_filled,
+            [InputField::Sequence, InputField::Bip32Derivation]
+        );
+        assert_eq!(report.inputs[0].signatures_added, 0);
+        assert_eq!(report.outputs.len(), 1);
+        assert_eq!(report.outputs[0].fields_filled, [OutputField::Bip32Derivation]);
+    }
+}
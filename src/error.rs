@@ -4,7 +4,18 @@
 
 use core::fmt;
 
-use bitcoin_internals::write_err;
+use bitcoin::bip32::Xpub;
+use bitcoin::{Amount, Txid};
+pub(crate) use bitcoin_internals::write_err;
+
+/// The standard error trait, from `core` when building without `std` (requires Rust 1.81).
+///
+/// With a new enough compiler `std::error::Error` is a re-export of `core::error::Error` so error
+/// sources are chained the same for both `std` and `no_std` users.
+#[cfg(all(not(feature = "std"), rust_v_1_81))]
+pub(crate) use core::error::Error as StdError;
+#[cfg(feature = "std")]
+pub(crate) use std::error::Error as StdError;
 
 /// Unable to determine lock time, multiple inputs have conflicting locking requirements.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -19,8 +30,8 @@ impl fmt::Display for DetermineLockTimeError {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for DetermineLockTimeError {}
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for DetermineLockTimeError {}
 
 /// Error when passing an un-modifiable PSBT to a `Constructor`.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -43,9 +54,9 @@ impl fmt::Display for PsbtNotModifiableError {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for PsbtNotModifiableError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for PsbtNotModifiableError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use PsbtNotModifiableError::*;
 
         match *self {
@@ -74,8 +85,8 @@ impl fmt::Display for InputsNotModifiableError {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for InputsNotModifiableError {}
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for InputsNotModifiableError {}
 
 /// Error when passing an PSBT with outputs not modifiable to an output adding `Constructor`.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -88,9 +99,9 @@ impl fmt::Display for OutputsNotModifiableError {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for OutputsNotModifiableError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for OutputsNotModifiableError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> { None }
 }
 
 /// An error getting the funding transaction for this input.
@@ -106,6 +117,8 @@ pub enum FundingUtxoError {
     },
     /// No funding utxo found.
     MissingUtxo,
+    /// The witness utxo does not match the spent output of the non-witness utxo.
+    Mismatch,
 }
 
 impl fmt::Display for FundingUtxoError {
@@ -115,17 +128,586 @@ impl fmt::Display for FundingUtxoError {
         match *self {
             OutOfBounds { vout, len } => write!(f, "vout {} out of bounds for tx list len: {}", vout, len),
             MissingUtxo => write!(f, "no funding utxo found"),
+            Mismatch => write!(f, "witness utxo does not match the non-witness utxo spent output"),
         }
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for FundingUtxoError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for FundingUtxoError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use FundingUtxoError::*;
 
         match *self {
-            OutOfBounds { .. } | MissingUtxo => None,
+            OutOfBounds { .. } | MissingUtxo | Mismatch => None,
+        }
+    }
+}
+
+/// Error adding an input to a PSBT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AddInputError {
+    /// The PSBT already has an input spending the same outpoint.
+    DuplicateInput {
+        /// The txid of the outpoint.
+        previous_txid: Txid,
+        /// The vout of the outpoint.
+        spent_output_index: u32,
+    },
+    /// The input's `spent_output_index` is out of bounds for its `non_witness_utxo`.
+    FundingUtxo(FundingUtxoError),
+}
+
+impl fmt::Display for AddInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use AddInputError::*;
+
+        match *self {
+            DuplicateInput { previous_txid, spent_output_index } => write!(
+                f,
+                "PSBT already has an input spending {}:{}",
+                previous_txid, spent_output_index
+            ),
+            FundingUtxo(ref e) => write_err!(f, "add input funding utxo"; e),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for AddInputError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use AddInputError::*;
+
+        match *self {
+            DuplicateInput { .. } => None,
+            FundingUtxo(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<FundingUtxoError> for AddInputError {
+    fn from(e: FundingUtxoError) -> Self { Self::FundingUtxo(e) }
+}
+
+/// Error accessing an input or output by index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IndexOutOfBoundsError {
+    /// The index is out of bounds for the `psbt.inputs` vector.
+    Inputs {
+        /// Attempted index access.
+        index: usize,
+        /// Length of the PSBT inputs vector.
+        length: usize,
+    },
+    /// The index is out of bounds for the `psbt.outputs` vector.
+    Outputs {
+        /// Attempted index access.
+        index: usize,
+        /// Length of the PSBT outputs vector.
+        length: usize,
+    },
+}
+
+impl fmt::Display for IndexOutOfBoundsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use IndexOutOfBoundsError::*;
+
+        match *self {
+            Inputs { ref index, ref length } => write!(
+                f,
+                "index {} is out-of-bounds for PSBT inputs vector length {}",
+                index, length
+            ),
+            Outputs { ref index, ref length } => write!(
+                f,
+                "index {} is out-of-bounds for PSBT outputs vector length {}",
+                index, length
+            ),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for IndexOutOfBoundsError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use IndexOutOfBoundsError::*;
+
+        match *self {
+            Inputs { .. } | Outputs { .. } => None,
+        }
+    }
+}
+
+/// Error combining two PSBTs, the global xpubs have inconsistent key sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct InconsistentKeySourcesError {
+    /// The xpub with inconsistent key sources.
+    pub xpub: Xpub,
+    /// The kind of inconsistency.
+    pub kind: XpubInconsistency,
+}
+
+impl fmt::Display for InconsistentKeySourcesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "combining PSBT, key-source conflict for xpub {}: {}", self.xpub, self.kind)
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for InconsistentKeySourcesError {}
+
+/// The kind of key source inconsistency found when combining global xpubs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum XpubInconsistency {
+    /// The derivation paths are equal but the master key fingerprints are not.
+    FingerprintMismatch,
+    /// The derivation paths are the same length but are not equal.
+    PathLengthMismatch,
+    /// The derivation paths have different lengths but the shorter is not a suffix of the longer.
+    PathNotSuffix,
+}
+
+impl fmt::Display for XpubInconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use XpubInconsistency::*;
+
+        match *self {
+            FingerprintMismatch => f.write_str("fingerprint mismatch"),
+            PathLengthMismatch => f.write_str("derivation paths of the same length differ"),
+            PathNotSuffix => f.write_str("shorter derivation path is not a suffix of the longer"),
         }
     }
 }
+
+/// An input lock time field, used to report which field conflicts when combining.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LockTimeField {
+    /// The `min_time` field (PSBT_IN_REQUIRED_TIME_LOCKTIME).
+    MinTime,
+    /// The `min_height` field (PSBT_IN_REQUIRED_HEIGHT_LOCKTIME).
+    MinHeight,
+}
+
+impl fmt::Display for LockTimeField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use LockTimeField::*;
+
+        match *self {
+            MinTime => f.write_str("min_time"),
+            MinHeight => f.write_str("min_height"),
+        }
+    }
+}
+
+/// An input or output script field, used to report which field conflicts when combining.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScriptField {
+    /// The `redeem_script` field (PSBT_IN_REDEEM_SCRIPT or PSBT_OUT_REDEEM_SCRIPT).
+    Redeem,
+    /// The `witness_script` field (PSBT_IN_WITNESS_SCRIPT or PSBT_OUT_WITNESS_SCRIPT).
+    Witness,
+}
+
+impl fmt::Display for ScriptField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ScriptField::*;
+
+        match *self {
+            Redeem => f.write_str("redeem_script"),
+            Witness => f.write_str("witness_script"),
+        }
+    }
+}
+
+/// The PSBT input and output counts are inconsistent with the inputs and outputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SanityError {
+    /// The `input_count` field does not match the length of the inputs vector.
+    InputCountMismatch {
+        /// The value of the `input_count` field.
+        count: usize,
+        /// The length of the inputs vector.
+        len: usize,
+    },
+    /// The `output_count` field does not match the length of the outputs vector.
+    OutputCountMismatch {
+        /// The value of the `output_count` field.
+        count: usize,
+        /// The length of the outputs vector.
+        len: usize,
+    },
+}
+
+impl fmt::Display for SanityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SanityError::*;
+
+        match *self {
+            InputCountMismatch { count, len } =>
+                write!(f, "input count {} does not match number of inputs {}", count, len),
+            OutputCountMismatch { count, len } =>
+                write!(f, "output count {} does not match number of outputs {}", count, len),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for SanityError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use SanityError::*;
+
+        match *self {
+            InputCountMismatch { .. } | OutputCountMismatch { .. } => None,
+        }
+    }
+}
+
+/// Error merging one PSBT into another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MergeError {
+    /// The PSBT being merged into is not modifiable.
+    NotModifiable(PsbtNotModifiableError),
+    /// The PSBT being merged from is not modifiable.
+    OtherNotModifiable(PsbtNotModifiableError),
+    /// The PSBTs do not have the same number of inputs.
+    InputCountMismatch {
+        /// Attempted to merge into a PSBT with `this` many inputs.
+        this: usize,
+        /// From a PSBT with `that` many inputs.
+        that: usize,
+    },
+    /// The inputs at `index` do not spend the same outpoint.
+    OutpointMismatch {
+        /// The index of the mismatched inputs.
+        index: usize,
+    },
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use MergeError::*;
+
+        match *self {
+            NotModifiable(ref e) => write_err!(f, "merge into PSBT"; e),
+            OtherNotModifiable(ref e) => write_err!(f, "merge from PSBT"; e),
+            InputCountMismatch { this, that } =>
+                write!(f, "merge two PSBTs with different input counts: {} {}", this, that),
+            OutpointMismatch { index } =>
+                write!(f, "merge two PSBTs with different outpoints for input {}", index),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for MergeError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use MergeError::*;
+
+        match *self {
+            NotModifiable(ref e) => Some(e),
+            OtherNotModifiable(ref e) => Some(e),
+            InputCountMismatch { .. } | OutpointMismatch { .. } => None,
+        }
+    }
+}
+
+/// Error replacing an input in a PSBT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReplaceError {
+    /// The PSBT does not have the inputs modifiable flag set.
+    NotModifiable(InputsNotModifiableError),
+    /// The input index is out of bounds.
+    IndexOutOfBounds(IndexOutOfBoundsError),
+}
+
+impl fmt::Display for ReplaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ReplaceError::*;
+
+        match *self {
+            NotModifiable(ref e) => write_err!(f, "replace input"; e),
+            IndexOutOfBounds(ref e) => write_err!(f, "replace input"; e),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for ReplaceError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use ReplaceError::*;
+
+        match *self {
+            NotModifiable(ref e) => Some(e),
+            IndexOutOfBounds(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<InputsNotModifiableError> for ReplaceError {
+    fn from(e: InputsNotModifiableError) -> Self { Self::NotModifiable(e) }
+}
+
+impl From<IndexOutOfBoundsError> for ReplaceError {
+    fn from(e: IndexOutOfBoundsError) -> Self { Self::IndexOutOfBounds(e) }
+}
+
+/// Error removing an input from a PSBT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RemoveError {
+    /// The PSBT does not have the inputs modifiable flag set.
+    NotModifiable(InputsNotModifiableError),
+    /// The PSBT has the SIGHASH_SINGLE flag set so the inputs can not be reordered.
+    SighashSingle,
+    /// The input index is out of bounds.
+    IndexOutOfBounds(IndexOutOfBoundsError),
+}
+
+impl fmt::Display for RemoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use RemoveError::*;
+
+        match *self {
+            NotModifiable(ref e) => write_err!(f, "remove input"; e),
+            SighashSingle =>
+                write!(f, "remove input: SIGHASH_SINGLE flag is set, inputs can not be reordered"),
+            IndexOutOfBounds(ref e) => write_err!(f, "remove input"; e),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for RemoveError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use RemoveError::*;
+
+        match *self {
+            NotModifiable(ref e) => Some(e),
+            SighashSingle => None,
+            IndexOutOfBounds(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<InputsNotModifiableError> for RemoveError {
+    fn from(e: InputsNotModifiableError) -> Self { Self::NotModifiable(e) }
+}
+
+impl From<IndexOutOfBoundsError> for RemoveError {
+    fn from(e: IndexOutOfBoundsError) -> Self { Self::IndexOutOfBounds(e) }
+}
+
+/// Error calculating the fee for a PSBT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FeeError {
+    /// Unable to get the funding UTXO for an input.
+    Funding(FundingUtxoError),
+    /// The sum of the output amounts is greater than the sum of the input amounts.
+    NegativeFee {
+        /// The amount by which the output amounts exceed the input amounts.
+        deficit: Amount,
+    },
+    /// Integer overflow summing the input or output amounts, or the sum exceeds
+    /// [`Amount::MAX_MONEY`].
+    Overflow,
+}
+
+impl fmt::Display for FeeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use FeeError::*;
+
+        match *self {
+            Funding(ref e) => write_err!(f, "fee funding utxo"; e),
+            NegativeFee { deficit } =>
+                write!(f, "output amounts exceed input amounts by {}", deficit),
+            Overflow => write!(f, "integer overflow summing amounts"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for FeeError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use FeeError::*;
+
+        match *self {
+            Funding(ref e) => Some(e),
+            NegativeFee { .. } | Overflow => None,
+        }
+    }
+}
+
+impl From<FundingUtxoError> for FeeError {
+    fn from(e: FundingUtxoError) -> Self { Self::Funding(e) }
+}
+
+/// Error estimating the weight of an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WeightError {
+    /// Unable to get the funding UTXO for an input.
+    FundingUtxo {
+        /// The index of the input.
+        input_index: usize,
+        /// The underlying error.
+        error: FundingUtxoError,
+    },
+    /// The input is not finalized and its funding script type is not supported.
+    UnsupportedScript {
+        /// The index of the input.
+        input_index: usize,
+    },
+}
+
+impl fmt::Display for WeightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use WeightError::*;
+
+        match *self {
+            FundingUtxo { input_index, ref error } =>
+                write_err!(f, "weight funding utxo for input {}", input_index; error),
+            UnsupportedScript { input_index } =>
+                write!(f, "unable to estimate weight of input {}, unsupported script", input_index),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for WeightError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use WeightError::*;
+
+        match *self {
+            FundingUtxo { ref error, .. } => Some(error),
+            UnsupportedScript { .. } => None,
+        }
+    }
+}
+
+/// Error checking if an input requires a `non_witness_utxo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NonWitnessUtxoError {
+    /// The input index is out of bounds.
+    IndexOutOfBounds(IndexOutOfBoundsError),
+    /// Unable to get the funding UTXO for the input.
+    FundingUtxo(FundingUtxoError),
+}
+
+impl fmt::Display for NonWitnessUtxoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use NonWitnessUtxoError::*;
+
+        match *self {
+            IndexOutOfBounds(ref e) => write_err!(f, "non-witness utxo requirement"; e),
+            FundingUtxo(ref e) => write_err!(f, "non-witness utxo requirement"; e),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for NonWitnessUtxoError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use NonWitnessUtxoError::*;
+
+        match *self {
+            IndexOutOfBounds(ref e) => Some(e),
+            FundingUtxo(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<IndexOutOfBoundsError> for NonWitnessUtxoError {
+    fn from(e: IndexOutOfBoundsError) -> Self { Self::IndexOutOfBounds(e) }
+}
+
+impl From<FundingUtxoError> for NonWitnessUtxoError {
+    fn from(e: FundingUtxoError) -> Self { Self::FundingUtxo(e) }
+}
+
+/// Attempted to set an invalid transaction version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TxVersionError;
+
+impl fmt::Display for TxVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("transaction version 0 is invalid")
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for TxVersionError {}
+
+/// Error validating a fully constructed PSBT or input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// The input and output counts are inconsistent.
+    Sanity(SanityError),
+    /// Unable to determine the lock time.
+    DetermineLockTime(DetermineLockTimeError),
+    /// Two or more inputs spend the same outpoint.
+    DuplicateInputs,
+    /// An input with a `SIGHASH_SINGLE` signature has no corresponding output.
+    SighashSinglePairing {
+        /// The index of the input with no corresponding output.
+        input_index: usize,
+    },
+    /// The funding UTXO of an input is invalid.
+    FundingUtxo(FundingUtxoError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use BuildError::*;
+
+        match *self {
+            Sanity(ref e) => write_err!(f, "build sanity check"; e),
+            DetermineLockTime(ref e) => write_err!(f, "build lock time"; e),
+            DuplicateInputs => write!(f, "two or more inputs spend the same outpoint"),
+            SighashSinglePairing { input_index } => write!(
+                f,
+                "input {} has a SIGHASH_SINGLE signature but no corresponding output",
+                input_index
+            ),
+            FundingUtxo(ref e) => write_err!(f, "build input"; e),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for BuildError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use BuildError::*;
+
+        match *self {
+            Sanity(ref e) => Some(e),
+            DetermineLockTime(ref e) => Some(e),
+            FundingUtxo(ref e) => Some(e),
+            DuplicateInputs | SighashSinglePairing { .. } => None,
+        }
+    }
+}
+
+impl From<SanityError> for BuildError {
+    fn from(e: SanityError) -> Self { Self::Sanity(e) }
+}
+
+impl From<DetermineLockTimeError> for BuildError {
+    fn from(e: DetermineLockTimeError) -> Self { Self::DetermineLockTime(e) }
+}
+
+impl From<FundingUtxoError> for BuildError {
+    fn from(e: FundingUtxoError) -> Self { Self::FundingUtxo(e) }
+}
@@ -2,17 +2,24 @@
 
 use core::fmt;
 
-use bitcoin::bip32::KeySource;
+use bitcoin::bip32::{Fingerprint, KeySource};
+use bitcoin::hashes::Hash as _;
 use bitcoin::hashes::{hash160, ripemd160, sha256, sha256d};
 use bitcoin::key::XOnlyPublicKey;
 use bitcoin::psbt::{raw, PsbtSighashType};
-use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TapNodeHash};
+use bitcoin::sighash::{EcdsaSighashType, NonStandardSighashTypeError, TapSighashType};
+use bitcoin::taproot::{
+    ControlBlock, LeafVersion, TapLeafHash, TapNodeHash, TapTree, TaprootSpendInfo,
+};
 use bitcoin::{
-    absolute, ecdsa, secp256k1, taproot, PublicKey, ScriptBuf, Sequence, Transaction, TxOut, Txid,
-    Witness,
+    absolute, ecdsa, relative, secp256k1, taproot, OutPoint, PublicKey, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Txid, Weight, Witness, WitnessVersion,
 };
 
-use crate::prelude::BTreeMap;
+use crate::error::{BuildError, FundingUtxoError, LockTimeField, ScriptField};
+#[cfg(any(feature = "std", rust_v_1_81))]
+use crate::error::StdError;
+use crate::internal_prelude::{BTreeMap, Vec};
 
 /// A PSBT input guaranteed to be valid for PSBT version 2.
 ///
@@ -118,16 +125,105 @@ pub struct Input {
 
     /// Taproot Merkle root hash.
     pub tap_merkle_root: Option<TapNodeHash>,
+
+    /// Map of MuSig2 aggregate keys to the participant keys that were aggregated (BIP-373).
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq"))]
+    pub musig2_participant_pubkeys: BTreeMap<secp256k1::PublicKey, Vec<secp256k1::PublicKey>>,
+
+    /// Map of `<participant key>|<aggregate key>|<leaf hash>` to the participant's 66 byte MuSig2
+    /// public nonce (BIP-373).
+    ///
+    /// The leaf hash is `None` for key path spends.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq_byte_values"))]
+    pub musig2_pub_nonces: BTreeMap<Musig2Key, Vec<u8>>,
+
+    /// Map of `<participant key>|<aggregate key>|<leaf hash>` to the participant's 32 byte MuSig2
+    /// partial signature (BIP-373).
+    ///
+    /// The leaf hash is `None` for key path spends.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq_byte_values"))]
+    pub musig2_partial_sigs: BTreeMap<Musig2Key, Vec<u8>>,
+
+    /// Map of silent payment scan keys to the 33 byte ECDH share for this input (BIP-375).
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq"))]
+    pub sp_ecdh_share: BTreeMap<secp256k1::PublicKey, secp256k1::PublicKey>,
+
+    /// Map of silent payment scan keys to the 64 byte DLEQ proof of the ECDH share (BIP-375).
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq_byte_values"))]
+    pub sp_dleq_proof: BTreeMap<secp256k1::PublicKey, Vec<u8>>,
+
+    /// Proprietary key-value pairs for this input.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq_byte_values"))]
+    pub proprietary: BTreeMap<raw::ProprietaryKey, Vec<u8>>,
+
+    /// Unknown key-value pairs for this input.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq_byte_values"))]
+    pub unknown: BTreeMap<raw::Key, Vec<u8>>,
 }
 
+/// The key of the MuSig2 public nonce and partial signature maps.
+///
+/// `(participant key, aggregate key, leaf hash)`, the leaf hash is `None` for key path spends.
+pub type Musig2Key = (secp256k1::PublicKey, secp256k1::PublicKey, Option<TapLeafHash>);
+
+/// Key type of the PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS field.
+const PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS: u8 = 0x1a;
+/// Key type of the PSBT_IN_MUSIG2_PUB_NONCE field.
+const PSBT_IN_MUSIG2_PUB_NONCE: u8 = 0x1b;
+/// Key type of the PSBT_IN_MUSIG2_PARTIAL_SIG field.
+const PSBT_IN_MUSIG2_PARTIAL_SIG: u8 = 0x1c;
+/// Key type of the PSBT_IN_SP_ECDH_SHARE field.
+const PSBT_IN_SP_ECDH_SHARE: u8 = 0x1d;
+/// Key type of the PSBT_IN_SP_DLEQ field.
+const PSBT_IN_SP_DLEQ: u8 = 0x1e;
+
 impl Input {
+    /// Creates a new `Input` that spends `previous_output`, all other fields are empty.
+    ///
+    /// See [`InputBuilder`] for constructing an input with additional fields set.
+    pub fn new(previous_output: &OutPoint) -> Self {
+        Input {
+            previous_txid: previous_output.txid,
+            spent_output_index: previous_output.vout,
+            sequence: None,
+            min_time: None,
+            min_height: None,
+            non_witness_utxo: None,
+            witness_utxo: None,
+            partial_sigs: BTreeMap::new(),
+            sighash_type: None,
+            redeem_script: None,
+            witness_script: None,
+            bip32_derivation: BTreeMap::new(),
+            final_script_sig: None,
+            final_script_witness: None,
+            ripemd160_preimages: BTreeMap::new(),
+            sha256_preimages: BTreeMap::new(),
+            hash160_preimages: BTreeMap::new(),
+            hash256_preimages: BTreeMap::new(),
+            tap_key_sig: None,
+            tap_script_sigs: BTreeMap::new(),
+            tap_scripts: BTreeMap::new(),
+            tap_key_origins: BTreeMap::new(),
+            tap_internal_key: None,
+            tap_merkle_root: None,
+            musig2_participant_pubkeys: BTreeMap::new(),
+            musig2_pub_nonces: BTreeMap::new(),
+            musig2_partial_sigs: BTreeMap::new(),
+            sp_ecdh_share: BTreeMap::new(),
+            sp_dleq_proof: BTreeMap::new(),
+            proprietary: BTreeMap::new(),
+            unknown: BTreeMap::new(),
+        }
+    }
+
     pub(crate) fn from_v2(input: bitcoin::psbt::Input) -> Result<Input, V2InvalidError> {
-        assert_is_valid_v2()?;
+        assert_is_valid_v2(&input)?;
 
         let previous_txid = input.previous_txid.unwrap();
         let spent_output_index = input.spent_output_index.unwrap();
 
-        Ok(Input {
+        let mut input = Input {
             non_witness_utxo: input.non_witness_utxo,
             witness_utxo: input.witness_utxo,
             partial_sigs: input.partial_sigs,
@@ -152,19 +248,29 @@ impl Input {
             tap_key_origins: input.tap_key_origins,
             tap_internal_key: input.tap_internal_key,
             tap_merkle_root: input.tap_merkle_root,
-        })
+            musig2_participant_pubkeys: BTreeMap::new(),
+            musig2_pub_nonces: BTreeMap::new(),
+            musig2_partial_sigs: BTreeMap::new(),
+            sp_ecdh_share: BTreeMap::new(),
+            sp_dleq_proof: BTreeMap::new(),
+            proprietary: input.proprietary,
+            unknown: input.unknown,
+        };
+        input.extract_musig2_fields();
+        input.extract_silent_payment_fields();
+        Ok(input)
     }
 
     pub(crate) fn from_v0(
         input: bitcoin::psbt::Input,
         prevout: &OutPoint,
     ) -> Result<Input, V0InvalidError> {
-        assert_is_valid_v0()?;
-        
+        assert_is_valid_v0(&input)?;
+
         let previous_txid = prevout.txid;
         let spent_output_index = prevout.vout;
-        
-        Ok(Input {
+
+        let mut input = Input {
             non_witness_utxo: input.non_witness_utxo,
             witness_utxo: input.witness_utxo,
             partial_sigs: input.partial_sigs,
@@ -189,11 +295,23 @@ impl Input {
             tap_key_origins: input.tap_key_origins,
             tap_internal_key: input.tap_internal_key,
             tap_merkle_root: input.tap_merkle_root,
-        })
+            musig2_participant_pubkeys: BTreeMap::new(),
+            musig2_pub_nonces: BTreeMap::new(),
+            musig2_partial_sigs: BTreeMap::new(),
+            sp_ecdh_share: BTreeMap::new(),
+            sp_dleq_proof: BTreeMap::new(),
+            proprietary: input.proprietary,
+            unknown: input.unknown,
+        };
+        input.extract_musig2_fields();
+        input.extract_silent_payment_fields();
+        Ok(input)
     }
     
     // Converts this input to a `rust-bitcoin` one.
-    pub(crate) fn to_v2(self) -> bitcoin::psbt::Input {
+    pub(crate) fn to_v2(mut self) -> bitcoin::psbt::Input {
+        self.insert_musig2_fields();
+        self.insert_silent_payment_fields();
         bitcoin::psbt::Input {
             non_witness_utxo: self.non_witness_utxo,
             witness_utxo: self.witness_utxo,
@@ -209,7 +327,7 @@ impl Input {
             hash160_preimages: self.hash160_preimages,
             hash256_preimages: self.hash256_preimages,
             previous_txid: Some(self.previous_txid),
-            spent_output_index: Some(spent_output_index),
+            spent_output_index: Some(self.spent_output_index),
             sequence: self.sequence,
             min_time: self.min_time,
             min_height: self.min_height,
@@ -219,8 +337,8 @@ impl Input {
             tap_key_origins: self.tap_key_origins,
             tap_internal_key: self.tap_internal_key,
             tap_merkle_root: self.tap_merkle_root,
-            proprietary: BTeeMap::default(),
-            unknown: BTeeMap::default(),
+            proprietary: self.proprietary,
+            unknown: self.unknown,
         }
     }
 
@@ -230,15 +348,110 @@ impl Input {
         input.previous_txid = None;
         input.spent_output_index = None;
         input.sequence = None;
+        input.min_time = None;
         input.min_height = None;
-        input.max_height = None;
         input
     }
 
+    /// Moves the BIP-373 MuSig2 fields out of `unknown` and into their own maps.
+    ///
+    /// `rust-bitcoin` does not support the MuSig2 fields so parses them as unknown fields. Any
+    /// malformed MuSig2 field is left in `unknown`.
+    fn extract_musig2_fields(&mut self) {
+        for (key, value) in core::mem::take(&mut self.unknown) {
+            match key.type_value {
+                PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS => {
+                    if let (Some(aggregate), Some(participants)) =
+                        (parse_compressed_pubkey(&key.key), parse_compressed_pubkeys(&value))
+                    {
+                        self.musig2_participant_pubkeys.insert(aggregate, participants);
+                        continue;
+                    }
+                }
+                PSBT_IN_MUSIG2_PUB_NONCE if value.len() == 66 => {
+                    if let Some(k) = parse_musig2_key(&key.key) {
+                        self.musig2_pub_nonces.insert(k, value);
+                        continue;
+                    }
+                }
+                PSBT_IN_MUSIG2_PARTIAL_SIG if value.len() == 32 => {
+                    if let Some(k) = parse_musig2_key(&key.key) {
+                        self.musig2_partial_sigs.insert(k, value);
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            self.unknown.insert(key, value);
+        }
+    }
+
+    /// Moves the BIP-373 MuSig2 fields into `unknown` so they can be serialized by `rust-bitcoin`.
+    fn insert_musig2_fields(&mut self) {
+        for (aggregate, participants) in core::mem::take(&mut self.musig2_participant_pubkeys) {
+            let key = raw::Key {
+                type_value: PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS,
+                key: aggregate.serialize().to_vec(),
+            };
+            let value = participants.iter().flat_map(|pk| pk.serialize()).collect();
+            self.unknown.insert(key, value);
+        }
+        for (k, nonce) in core::mem::take(&mut self.musig2_pub_nonces) {
+            let key =
+                raw::Key { type_value: PSBT_IN_MUSIG2_PUB_NONCE, key: serialize_musig2_key(&k) };
+            self.unknown.insert(key, nonce);
+        }
+        for (k, sig) in core::mem::take(&mut self.musig2_partial_sigs) {
+            let key =
+                raw::Key { type_value: PSBT_IN_MUSIG2_PARTIAL_SIG, key: serialize_musig2_key(&k) };
+            self.unknown.insert(key, sig);
+        }
+    }
+
+    /// Moves the BIP-375 silent payment fields out of `unknown` and into their own maps.
+    ///
+    /// As for the MuSig2 fields, `rust-bitcoin` parses these as unknown fields. Any malformed
+    /// silent payment field is left in `unknown`.
+    fn extract_silent_payment_fields(&mut self) {
+        for (key, value) in core::mem::take(&mut self.unknown) {
+            match key.type_value {
+                PSBT_IN_SP_ECDH_SHARE => {
+                    if let (Some(scan_key), Some(share)) =
+                        (parse_compressed_pubkey(&key.key), parse_compressed_pubkey(&value))
+                    {
+                        self.sp_ecdh_share.insert(scan_key, share);
+                        continue;
+                    }
+                }
+                PSBT_IN_SP_DLEQ if value.len() == 64 => {
+                    if let Some(scan_key) = parse_compressed_pubkey(&key.key) {
+                        self.sp_dleq_proof.insert(scan_key, value);
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            self.unknown.insert(key, value);
+        }
+    }
+
+    /// Moves the BIP-375 silent payment fields into `unknown` so they can be serialized.
+    fn insert_silent_payment_fields(&mut self) {
+        for (scan_key, share) in core::mem::take(&mut self.sp_ecdh_share) {
+            let key =
+                raw::Key { type_value: PSBT_IN_SP_ECDH_SHARE, key: scan_key.serialize().to_vec() };
+            self.unknown.insert(key, share.serialize().to_vec());
+        }
+        for (scan_key, proof) in core::mem::take(&mut self.sp_dleq_proof) {
+            let key = raw::Key { type_value: PSBT_IN_SP_DLEQ, key: scan_key.serialize().to_vec() };
+            self.unknown.insert(key, proof);
+        }
+    }
+
     /// Returns a [`TxIn`] suitable for the PSBTv0 `unsigned_tx` field.
     pub(crate) fn unsigned_tx_in(&self) -> TxIn {
         TxIn {
-            previous_output: self.previous_output,
+            previous_output: OutPoint { txid: self.previous_txid, vout: self.spent_output_index },
             script_sig: ScriptBuf::default(),
             sequence: self.sequence.unwrap_or(Sequence::MAX),
             witness: Witness::default(),
@@ -263,6 +476,14 @@ impl Input {
         self.min_height.is_some() && self.min_time.is_none()
     }
 
+    /// Returns the BIP-68 relative lock time encoded in the sequence number, if any.
+    ///
+    /// Returns `None` if the sequence is not set (since it is assumed to be [`Sequence::MAX`]) or
+    /// if the sequence does not encode a relative lock time (i.e. the disable flag is set).
+    pub fn relative_lock_time(&self) -> Option<relative::LockTime> {
+        self.sequence.and_then(|seq| seq.to_relative_lock_time())
+    }
+
     /// Returns a reference to the funding utxo for this input.
     pub fn funding_utxo(&self) -> Result<&TxOut, FundingUtxoError> {
         if let Some(ref utxo) = self.witness_utxo {
@@ -275,6 +496,107 @@ impl Input {
         }
     }
 
+    /// Returns a reference to the funding utxo for this input, checking the UTXOs are consistent.
+    ///
+    /// Same as [`Self::funding_utxo`] but if both `witness_utxo` and `non_witness_utxo` are set
+    /// also checks that the witness UTXO is the output at `spent_output_index` of the non-witness
+    /// UTXO.
+    pub fn funding_utxo_checked(&self) -> Result<&TxOut, FundingUtxoError> {
+        if let (Some(utxo), Some(tx)) = (&self.witness_utxo, &self.non_witness_utxo) {
+            let vout = self.spent_output_index as usize;
+            let spent = tx
+                .output
+                .get(vout)
+                .ok_or(FundingUtxoError::OutOfBounds { vout, len: tx.output.len() })?;
+            if spent != utxo {
+                return Err(FundingUtxoError::Mismatch);
+            }
+        }
+        self.funding_utxo()
+    }
+
+    /// Returns the segwit version of the funding output, or `None` if it is not a segwit output.
+    ///
+    /// For P2SH outputs the `redeem_script` is used, if set, so that P2SH wrapped segwit outputs
+    /// return the version of the wrapped witness program.
+    pub fn witness_version(&self) -> Result<Option<WitnessVersion>, FundingUtxoError> {
+        let script_pubkey = &self.funding_utxo()?.script_pubkey;
+        if script_pubkey.is_p2sh() {
+            return Ok(self.redeem_script.as_ref().and_then(|s| s.witness_version()));
+        }
+        Ok(script_pubkey.witness_version())
+    }
+
+    /// Returns the witness UTXO for this input, computing it from the non-witness UTXO if needed.
+    ///
+    /// If `witness_utxo` is not set but `non_witness_utxo` is, the spent output is extracted from
+    /// the non-witness UTXO and stored in `witness_utxo` before being returned.
+    pub fn compute_witness_utxo(&mut self) -> Result<&TxOut, FundingUtxoError> {
+        if self.witness_utxo.is_none() {
+            let tx = self.non_witness_utxo.as_ref().ok_or(FundingUtxoError::MissingUtxo)?;
+            let vout = self.spent_output_index as usize;
+            let utxo = tx
+                .output
+                .get(vout)
+                .ok_or(FundingUtxoError::OutOfBounds { vout, len: tx.output.len() })?;
+            self.witness_utxo = Some(utxo.clone());
+        }
+        Ok(self.witness_utxo.as_ref().expect("witness_utxo is set above"))
+    }
+
+    /// Returns an iterator over the `bip32_derivation` keys sourced from master key `fp`.
+    pub fn keys_for_fingerprint(
+        &self,
+        fp: Fingerprint,
+    ) -> impl Iterator<Item = (&secp256k1::PublicKey, &KeySource)> {
+        self.bip32_derivation.iter().filter(move |(_, (fingerprint, _))| *fingerprint == fp)
+    }
+
+    /// Returns an iterator over the `tap_key_origins` keys sourced from master key `fp`.
+    pub fn tap_keys_for_fingerprint(
+        &self,
+        fp: Fingerprint,
+    ) -> impl Iterator<Item = (&XOnlyPublicKey, &(Vec<TapLeafHash>, KeySource))> {
+        self.tap_key_origins.iter().filter(move |(_, (_, (fingerprint, _)))| *fingerprint == fp)
+    }
+
+    /// Adds a taproot leaf script, and its control block, to `tap_scripts`.
+    pub fn add_tap_script(
+        &mut self,
+        control_block: ControlBlock,
+        script: ScriptBuf,
+        version: LeafVersion,
+    ) {
+        self.tap_scripts.insert(control_block, (script, version));
+    }
+
+    /// Adds a taproot leaf script from `tree` to `tap_scripts`, computing the control block.
+    ///
+    /// Also sets `tap_internal_key` and `tap_merkle_root` since these are required, along with the
+    /// control block, to sign a script path spend.
+    ///
+    /// # Errors
+    ///
+    /// If the `(script, version)` leaf is not in `tree`.
+    pub fn add_tap_script_from_tree<C: secp256k1::Verification>(
+        &mut self,
+        secp: &secp256k1::Secp256k1<C>,
+        internal_key: XOnlyPublicKey,
+        tree: &TapTree,
+        script: ScriptBuf,
+        version: LeafVersion,
+    ) -> Result<(), TapScriptNotInTreeError> {
+        let spend_info =
+            TaprootSpendInfo::from_node_info(secp, internal_key, tree.clone().into_node_info());
+        let leaf = (script, version);
+        let control_block = spend_info.control_block(&leaf).ok_or(TapScriptNotInTreeError)?;
+
+        self.tap_internal_key = Some(internal_key);
+        self.tap_merkle_root = spend_info.merkle_root();
+        self.tap_scripts.insert(control_block, leaf);
+        Ok(())
+    }
+
     /// Returns true if this input has been finalized.
     ///
     /// > It checks whether all inputs have complete scriptSigs and scriptWitnesses by checking for
@@ -286,6 +608,157 @@ impl Input {
         self.final_script_sig.is_some() && self.final_script_witness.is_some()
     }
 
+    /// Returns the ECDSA sighash type to use when signing this input.
+    ///
+    /// This is the `sighash_type` field if set, otherwise [`EcdsaSighashType::All`].
+    pub fn ecdsa_sighash_type(&self) -> Result<EcdsaSighashType, NonStandardSighashTypeError> {
+        match self.sighash_type {
+            Some(ty) => ty.ecdsa_hash_ty(),
+            None => Ok(EcdsaSighashType::All),
+        }
+    }
+
+    /// Returns the taproot sighash type to use when signing this input.
+    ///
+    /// This is the `sighash_type` field if set, otherwise [`TapSighashType::Default`]. A
+    /// `sighash_type` that is not a valid taproot sighash type also returns the default.
+    pub fn taproot_sighash_type(&self) -> TapSighashType {
+        self.sighash_type
+            .and_then(|ty| ty.taproot_hash_ty().ok())
+            .unwrap_or(TapSighashType::Default)
+    }
+
+    /// Returns the estimated weight this input contributes to a transaction.
+    ///
+    /// For finalized inputs the weight is calculated from the final scriptSig and scriptWitness.
+    /// Otherwise the weight is estimated from the funding script type, assuming a single key spend
+    /// (P2PKH, P2WPKH, P2SH-P2WPKH or P2TR key path), for other script types returns `None`.
+    ///
+    /// The segwit marker and flag bytes are not included since they are per transaction.
+    pub(crate) fn estimated_weight(&self) -> Result<Option<Weight>, FundingUtxoError> {
+        // Outpoint (36 bytes) and sequence number (4 bytes).
+        const BASE_SIZE: u64 = 40;
+
+        if let (Some(script_sig), Some(witness)) =
+            (&self.final_script_sig, &self.final_script_witness)
+        {
+            let script_sig_size = compact_size_len(script_sig.len()) + script_sig.len() as u64;
+            let witness_size = if witness.is_empty() { 0 } else { witness.size() as u64 };
+            return Ok(Some(Weight::from_wu((BASE_SIZE + script_sig_size) * 4 + witness_size)));
+        }
+
+        let script_pubkey = &self.funding_utxo()?.script_pubkey;
+
+        // Weights of the scriptSig (including length prefix) and the scriptWitness.
+        let (script_sig_size, witness_size) = if script_pubkey.is_p2pkh() {
+            // Push of 72 byte signature and 33 byte compressed key.
+            (1 + 107, 0)
+        } else if script_pubkey.is_p2wpkh() {
+            // Item count, 72 byte signature and 33 byte compressed key.
+            (1, 1 + 73 + 34)
+        } else if script_pubkey.is_p2sh()
+            && self.redeem_script.as_ref().map(|s| s.is_p2wpkh()).unwrap_or(false)
+        {
+            // Push of the 22 byte P2WPKH redeem script.
+            (1 + 23, 1 + 73 + 34)
+        } else if script_pubkey.is_p2tr() {
+            // Item count and 64 byte signature, plus a sighash byte if not `SIGHASH_DEFAULT`.
+            let sighash_byte = match self.taproot_sighash_type() {
+                TapSighashType::Default => 0,
+                _ => 1,
+            };
+            (1, 1 + 65 + sighash_byte)
+        } else {
+            return Ok(None);
+        };
+
+        Ok(Some(Weight::from_wu((BASE_SIZE + script_sig_size) * 4 + witness_size)))
+    }
+
+    /// Clears all signature data from this input, including any finalized scripts.
+    ///
+    /// Changing any data committed to by the sighash (e.g. the sequence number or the funding
+    /// UTXO) invalidates existing signatures, use this to reset the input ready for re-signing.
+    pub fn clear_signatures(&mut self) {
+        self.partial_sigs.clear();
+        self.tap_key_sig = None;
+        self.tap_script_sigs.clear();
+        self.musig2_partial_sigs.clear();
+        self.final_script_sig = None;
+        self.final_script_witness = None;
+    }
+
+    /// Removes data from this input that is redundant, see [`crate::Psbt::strip_for_transport`].
+    pub(crate) fn strip_for_transport(&mut self) {
+        self.drop_redundant_utxo();
+
+        if self.is_finalized() {
+            self.clear_finalized_data();
+        }
+    }
+
+    /// Drops one of the UTXOs if both are set.
+    ///
+    /// Segwit inputs keep the witness UTXO and legacy inputs keep the non-witness UTXO. A
+    /// finalized P2SH wrapped segwit input has no `redeem_script` so is detected by its witness.
+    fn drop_redundant_utxo(&mut self) {
+        if self.witness_utxo.is_none() || self.non_witness_utxo.is_none() {
+            return;
+        }
+
+        let has_witness = self.final_script_witness.as_ref().map_or(false, |w| !w.is_empty());
+        if self.spends_segwit() || has_witness {
+            self.non_witness_utxo = None;
+        } else {
+            self.witness_utxo = None;
+        }
+    }
+
+    /// Removes the data that a Finalizer removes once the final scripts are set (BIP-174).
+    ///
+    /// Only the outpoint, sequence, required lock times, UTXOs, final scripts, and proprietary and
+    /// unknown data are kept.
+    fn clear_finalized_data(&mut self) {
+        self.partial_sigs.clear();
+        self.sighash_type = None;
+        self.redeem_script = None;
+        self.witness_script = None;
+        self.bip32_derivation.clear();
+        self.ripemd160_preimages.clear();
+        self.sha256_preimages.clear();
+        self.hash160_preimages.clear();
+        self.hash256_preimages.clear();
+        self.tap_key_sig = None;
+        self.tap_script_sigs.clear();
+        self.tap_scripts.clear();
+        self.tap_key_origins.clear();
+        self.tap_internal_key = None;
+        self.tap_merkle_root = None;
+        self.musig2_participant_pubkeys.clear();
+        self.musig2_pub_nonces.clear();
+        self.musig2_partial_sigs.clear();
+        self.sp_ecdh_share.clear();
+        self.sp_dleq_proof.clear();
+    }
+
+    /// Returns true if any signature on this input uses a `SIGHASH_SINGLE` sighash type.
+    pub(crate) fn has_sighash_single_sig(&self) -> bool {
+        use EcdsaSighashType::{Single, SinglePlusAnyoneCanPay};
+
+        let ecdsa = self
+            .partial_sigs
+            .values()
+            .any(|sig| matches!(sig.sighash_type, Single | SinglePlusAnyoneCanPay));
+        let taproot = self.tap_key_sig.iter().chain(self.tap_script_sigs.values()).any(|sig| {
+            matches!(
+                sig.sighash_type,
+                TapSighashType::Single | TapSighashType::SinglePlusAnyoneCanPay
+            )
+        });
+
+        ecdsa || taproot
+    }
+
     /// TODO: Use this.
     #[allow(dead_code)]
     fn has_sig_data(&self) -> bool {
@@ -300,13 +773,15 @@ impl Input {
     /// case that this is a legacy input.
     ///
     /// The `final_script_sig` and `final_script_witness` should come from `miniscript`.
+    // TODO: Use this.
     #[cfg(feature = "miniscript")]
+    #[allow(dead_code)]
     pub(crate) fn finalize(
         &self,
         final_script_sig: ScriptBuf,
         final_script_witness: Witness,
     ) -> Result<Input, FinalizeError> {
-        debug_assert!(self.has_funding_utxo());
+        debug_assert!(self.funding_utxo().is_ok());
 
         let mut ret = Input {
             previous_txid: self.previous_txid,
@@ -326,7 +801,7 @@ impl Input {
             sighash_type: None,
             redeem_script: None,
             witness_script: None,
-            bip32_derivations: BTreeMap::new(),
+            bip32_derivation: BTreeMap::new(),
             ripemd160_preimages: BTreeMap::new(),
             sha256_preimages: BTreeMap::new(),
             hash160_preimages: BTreeMap::new(),
@@ -337,6 +812,15 @@ impl Input {
             tap_key_origins: BTreeMap::new(),
             tap_internal_key: None,
             tap_merkle_root: None,
+            musig2_participant_pubkeys: BTreeMap::new(),
+            musig2_pub_nonces: BTreeMap::new(),
+            musig2_partial_sigs: BTreeMap::new(),
+            sp_ecdh_share: BTreeMap::new(),
+            sp_dleq_proof: BTreeMap::new(),
+
+            // Keep data we do not understand, it may be needed by another entity.
+            proprietary: self.proprietary.clone(),
+            unknown: self.unknown.clone(),
         };
 
         // TODO: These errors should only trigger if there are bugs in this crate or miniscript.
@@ -369,6 +853,13 @@ impl Input {
     }
 
     /// Combines this [`Input`] with `other` (as described by BIP-174).
+    ///
+    /// If either input is finalized the combined input is finalized, the final scripts are taken
+    /// from the finalized input and the signatures, scripts, and key data of both inputs are
+    /// dropped (as removed by the Finalizer). If both are finalized their final scripts must match.
+    ///
+    /// If the combined input has both UTXOs only one is kept, segwit inputs keep the
+    /// `witness_utxo` and legacy inputs keep the `non_witness_utxo`.
     pub fn combine(&mut self, other: Self) -> Result<(), CombineError> {
         if self.previous_txid != other.previous_txid {
             return Err(CombineError::PreviousTxidMismatch {
@@ -384,41 +875,563 @@ impl Input {
             });
         }
 
-        // TODO: Should we keep any value other than Sequence::MAX since it is default?
+        // Common when a party only contributes data for some of the inputs.
+        if other.has_data() {
+            self.combine_data(other)?;
+        }
+
+        // Done after combining `redeem_script` since we need it to detect P2SH wrapped segwit.
+        self.drop_redundant_utxo();
+
+        if self.is_finalized() {
+            self.clear_finalized_data();
+        }
+
+        Ok(())
+    }
+
+    /// Combines all the fields of `other` other than the outpoint, see [`Self::combine`].
+    fn combine_data(&mut self, other: Self) -> Result<(), CombineError> {
+        let (this_finalized, that_finalized) = (self.is_finalized(), other.is_finalized());
+        if this_finalized
+            && that_finalized
+            && (self.final_script_sig != other.final_script_sig
+                || self.final_script_witness != other.final_script_witness)
+        {
+            return Err(CombineError::FinalizedConflict);
+        }
+
+        // The sequence number is committed to by every signature, both can not be right.
+        if let (Some(this), Some(that)) = (self.sequence, other.sequence) {
+            if this != that {
+                return Err(CombineError::SequenceConflict { this, that });
+            }
+        }
         v2_combine_option!(sequence, self, other);
+        // These feed into the lock time calculation so letting one silently win is not ok.
+        if self.min_time.is_some() && other.min_time.is_some() && self.min_time != other.min_time {
+            return Err(CombineError::LockTimeFieldConflict { field: LockTimeField::MinTime });
+        }
+        if self.min_height.is_some()
+            && other.min_height.is_some()
+            && self.min_height != other.min_height
+        {
+            return Err(CombineError::LockTimeFieldConflict { field: LockTimeField::MinHeight });
+        }
+        // The PSBTs disagree about the coin being spent, this is not a mergeable difference.
+        if let (Some(this), Some(that)) = (&self.witness_utxo, &other.witness_utxo) {
+            if this != that {
+                return Err(CombineError::WitnessUtxoMismatch);
+            }
+        }
+        if let (Some(this), Some(that)) = (&self.non_witness_utxo, &other.non_witness_utxo) {
+            if this.compute_txid() != that.compute_txid() {
+                return Err(CombineError::NonWitnessUtxoMismatch);
+            }
+        }
         v2_combine_option!(min_time, self, other);
         v2_combine_option!(min_height, self, other);
         v2_combine_option!(non_witness_utxo, self, other);
-
-        // TODO: Copied from v0, confirm this is correct.
-        if let (&None, Some(witness_utxo)) = (&self.witness_utxo, other.witness_utxo) {
-            self.witness_utxo = Some(witness_utxo);
-            self.non_witness_utxo = None; // Clear out any non-witness UTXO when we set a witness one
+        v2_combine_option!(witness_utxo, self, other);
+        v2_combine_map!(proprietary, self, other);
+        v2_combine_map!(unknown, self, other);
+
+        if this_finalized || that_finalized {
+            // The finalized data wins. Everything the Finalizer removes (signatures, scripts, key
+            // data, etc.) is ignored from both inputs and cleared by the caller.
+            if !this_finalized {
+                self.final_script_sig = other.final_script_sig;
+                self.final_script_witness = other.final_script_witness;
+            }
+            // Still needed to normalize the UTXOs of P2SH wrapped segwit inputs.
+            v2_combine_option!(redeem_script, self, other);
+            return Ok(());
         }
 
-        v2_combine_map!(partial_sigs, self, other);
-        // TODO: Why do we not combine sighash_type?
+        // A signer could otherwise sign against the wrong script.
+        if let (Some(this), Some(that)) = (&self.redeem_script, &other.redeem_script) {
+            if this != that {
+                return Err(CombineError::ScriptConflict { which: ScriptField::Redeem });
+            }
+        }
+        if let (Some(this), Some(that)) = (&self.witness_script, &other.witness_script) {
+            if this != that {
+                return Err(CombineError::ScriptConflict { which: ScriptField::Witness });
+            }
+        }
+        // Two valid signatures for the same key can differ, never silently overwrite one.
+        combine_map_checked(&mut self.partial_sigs, other.partial_sigs)
+            .map_err(|pubkey| CombineError::SignatureConflict { pubkey })?;
+        // Commonly set by the updater and not re-declared by later signers, if both are set they
+        // must agree since a signer uses it to choose the sighash to sign.
+        if let (Some(this), Some(that)) = (self.sighash_type, other.sighash_type) {
+            if this != that {
+                return Err(CombineError::SighashTypeConflict { this, that });
+            }
+        }
+        v2_combine_option!(sighash_type, self, other);
         v2_combine_option!(redeem_script, self, other);
         v2_combine_option!(witness_script, self, other);
-        v2_combine_map!(bip32_derivations, self, other);
+        v2_combine_map!(bip32_derivation, self, other);
         v2_combine_option!(final_script_sig, self, other);
         v2_combine_option!(final_script_witness, self, other);
         v2_combine_map!(ripemd160_preimages, self, other);
         v2_combine_map!(sha256_preimages, self, other);
         v2_combine_map!(hash160_preimages, self, other);
         v2_combine_map!(hash256_preimages, self, other);
+        if let (Some(this), Some(that)) = (&self.tap_key_sig, &other.tap_key_sig) {
+            if this != that {
+                let key = self.tap_internal_key.or(other.tap_internal_key);
+                return Err(CombineError::TaprootSignatureConflict { key, leaf_hash: None });
+            }
+        }
         v2_combine_option!(tap_key_sig, self, other);
-        v2_combine_map!(tap_script_sigs, self, other);
-        v2_combine_map!(tap_scripts, self, other);
-        v2_combine_map!(tap_key_origins, self, other);
+        combine_map_checked(&mut self.tap_script_sigs, other.tap_script_sigs).map_err(
+            |(key, leaf_hash)| CombineError::TaprootSignatureConflict {
+                key: Some(key),
+                leaf_hash: Some(leaf_hash),
+            },
+        )?;
+        // A silent overwrite here could lead to finalizing with the wrong script.
+        combine_map_checked(&mut self.tap_scripts, other.tap_scripts)
+            .map_err(|control_block| CombineError::TaprootScriptConflict { control_block })?;
+        combine_map_checked(&mut self.tap_key_origins, other.tap_key_origins)
+            .map_err(|key| CombineError::TaprootKeyOriginConflict { key })?;
         v2_combine_option!(tap_internal_key, self, other);
         v2_combine_option!(tap_merkle_root, self, other);
+        v2_combine_map!(musig2_participant_pubkeys, self, other);
+        v2_combine_map!(musig2_pub_nonces, self, other);
+        v2_combine_map!(musig2_partial_sigs, self, other);
+        v2_combine_map!(sp_ecdh_share, self, other);
+        v2_combine_map!(sp_dleq_proof, self, other);
 
         Ok(())
     }
 
+    /// Returns true if any field other than the outpoint (previous txid and output index) is set.
+    fn has_data(&self) -> bool {
+        self.sequence.is_some()
+            || self.min_time.is_some()
+            || self.min_height.is_some()
+            || self.non_witness_utxo.is_some()
+            || self.witness_utxo.is_some()
+            || !self.partial_sigs.is_empty()
+            || self.sighash_type.is_some()
+            || self.redeem_script.is_some()
+            || self.witness_script.is_some()
+            || !self.bip32_derivation.is_empty()
+            || self.final_script_sig.is_some()
+            || self.final_script_witness.is_some()
+            || !self.ripemd160_preimages.is_empty()
+            || !self.sha256_preimages.is_empty()
+            || !self.hash160_preimages.is_empty()
+            || !self.hash256_preimages.is_empty()
+            || self.tap_key_sig.is_some()
+            || !self.tap_script_sigs.is_empty()
+            || !self.tap_scripts.is_empty()
+            || !self.tap_key_origins.is_empty()
+            || self.tap_internal_key.is_some()
+            || self.tap_merkle_root.is_some()
+            || !self.musig2_participant_pubkeys.is_empty()
+            || !self.musig2_pub_nonces.is_empty()
+            || !self.musig2_partial_sigs.is_empty()
+            || !self.sp_ecdh_share.is_empty()
+            || !self.sp_dleq_proof.is_empty()
+            || !self.proprietary.is_empty()
+            || !self.unknown.is_empty()
+    }
+
+    /// Returns true if the funding UTXO is a segwit output (including P2SH wrapped segwit).
+    ///
+    /// Returns false if there is no funding UTXO.
+    pub(crate) fn spends_segwit(&self) -> bool {
+        let script_pubkey = match self.funding_utxo() {
+            Ok(utxo) => &utxo.script_pubkey,
+            Err(_) => return false,
+        };
+
+        if script_pubkey.is_p2sh() {
+            self.redeem_script.as_ref().map(|s| s.is_witness_program()).unwrap_or(false)
+        } else {
+            script_pubkey.is_witness_program()
+        }
+    }
+}
+
+impl From<Input> for bitcoin::psbt::Input {
+    /// Converts to a `rust-bitcoin` input with the PSBT version 2 fields set.
+    fn from(input: Input) -> Self { input.to_v2() }
+}
+
+impl TryFrom<bitcoin::psbt::Input> for Input {
+    type Error = V2InvalidError;
+
+    /// Converts from a `rust-bitcoin` input, the PSBT version 2 required fields must be set.
+    fn try_from(input: bitcoin::psbt::Input) -> Result<Self, Self::Error> { Self::from_v2(input) }
+}
+
+/// Builder for an [`Input`].
+///
+/// # Examples
+///
+/// ```
+/// use psbt_v2::bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, TxOut};
+/// use psbt_v2::InputBuilder;
+///
+/// let utxo = TxOut { value: Amount::from_sat(10_000), script_pubkey: ScriptBuf::new() };
+/// let input = InputBuilder::new(&OutPoint::COINBASE_PREVOUT)
+///     .witness_utxo(utxo)
+///     .sequence(Sequence::ENABLE_RBF_NO_LOCKTIME)
+///     .build()?;
+/// # Ok::<_, psbt_v2::BuildError>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InputBuilder(Input);
+
+impl InputBuilder {
+    /// Creates a new builder for an input that spends `previous_output`.
+    pub fn new(previous_output: &OutPoint) -> Self { Self(Input::new(previous_output)) }
+
+    /// Sets the `witness_utxo` field.
+    pub fn witness_utxo(mut self, utxo: TxOut) -> Self {
+        self.0.witness_utxo = Some(utxo);
+        self
+    }
+
+    /// Sets the `non_witness_utxo` field.
+    pub fn non_witness_utxo(mut self, tx: Transaction) -> Self {
+        self.0.non_witness_utxo = Some(tx);
+        self
+    }
+
+    /// Sets the `redeem_script` field.
+    pub fn redeem_script(mut self, script: ScriptBuf) -> Self {
+        self.0.redeem_script = Some(script);
+        self
+    }
+
+    /// Sets the `witness_script` field.
+    pub fn witness_script(mut self, script: ScriptBuf) -> Self {
+        self.0.witness_script = Some(script);
+        self
+    }
+
+    /// Sets the `sighash_type` field.
+    pub fn sighash_type(mut self, sighash_type: PsbtSighashType) -> Self {
+        self.0.sighash_type = Some(sighash_type);
+        self
+    }
+
+    /// Adds `pk` and its `key_source` to the `bip32_derivation` map.
+    pub fn bip32_derivation(mut self, pk: secp256k1::PublicKey, key_source: KeySource) -> Self {
+        self.0.bip32_derivation.insert(pk, key_source);
+        self
+    }
+
+    /// Sets the `sequence` field.
+    pub fn sequence(mut self, sequence: Sequence) -> Self {
+        self.0.sequence = Some(sequence);
+        self
+    }
+
+    /// Validates and returns the finished [`Input`].
+    ///
+    /// # Errors
+    ///
+    /// If `non_witness_utxo` is set and the spent output index is out of bounds, or if both
+    /// `witness_utxo` and `non_witness_utxo` are set and they disagree on the spent output.
+    pub fn build(self) -> Result<Input, BuildError> {
+        let input = self.0;
+        if input.witness_utxo.is_some() || input.non_witness_utxo.is_some() {
+            let _ = input.funding_utxo_checked()?;
+        }
+        Ok(input)
+    }
+}
+
+/// Combines `that` map into `this` map.
+///
+/// Returns the first key that is in both maps with different values, in which case `this` may
+/// have been partially extended.
+fn combine_map_checked<K: Ord + Clone, V: PartialEq>(
+    this: &mut BTreeMap<K, V>,
+    that: BTreeMap<K, V>,
+) -> Result<(), K> {
+    for (key, value) in that {
+        match this.get(&key) {
+            Some(existing) if *existing != value => return Err(key),
+            Some(_) => {}
+            None => {
+                this.insert(key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the length of the compact size encoding of `n`.
+pub(crate) fn compact_size_len(n: usize) -> u64 {
+    match n {
+        0..=0xFC => 1,
+        0xFD..=0xFFFF => 3,
+        0x10000..=0xFFFF_FFFF => 5,
+        _ => 9,
+    }
+}
+
+/// Parses a 33 byte compressed public key.
+fn parse_compressed_pubkey(bytes: &[u8]) -> Option<secp256k1::PublicKey> {
+    if bytes.len() != 33 {
+        return None;
+    }
+    secp256k1::PublicKey::from_slice(bytes).ok()
+}
+
+/// Parses a non-empty list of 33 byte compressed public keys.
+fn parse_compressed_pubkeys(bytes: &[u8]) -> Option<Vec<secp256k1::PublicKey>> {
+    if bytes.is_empty() || bytes.len() % 33 != 0 {
+        return None;
+    }
+    bytes.chunks(33).map(parse_compressed_pubkey).collect()
+}
+
+/// Parses a `<participant key>|<aggregate key>|<optional leaf hash>` MuSig2 key.
+fn parse_musig2_key(bytes: &[u8]) -> Option<Musig2Key> {
+    if bytes.len() != 66 && bytes.len() != 98 {
+        return None;
+    }
+    let participant = parse_compressed_pubkey(&bytes[..33])?;
+    let aggregate = parse_compressed_pubkey(&bytes[33..66])?;
+    let leaf_hash = if bytes.len() == 98 {
+        let hash: [u8; 32] = bytes[66..].try_into().expect("slice is 32 bytes long");
+        Some(TapLeafHash::from_byte_array(hash))
+    } else {
+        None
+    };
+    Some((participant, aggregate, leaf_hash))
+}
+
+/// Serializes a MuSig2 key, the inverse of [`parse_musig2_key`].
+fn serialize_musig2_key((participant, aggregate, leaf_hash): &Musig2Key) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(98);
+    bytes.extend_from_slice(&participant.serialize());
+    bytes.extend_from_slice(&aggregate.serialize());
+    if let Some(leaf_hash) = leaf_hash {
+        bytes.extend_from_slice(leaf_hash.as_byte_array());
+    }
+    bytes
+}
+
+/// Error combining two input maps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CombineError {
+    /// The previous txids are not the same.
+    PreviousTxidMismatch {
+        /// Attempted to combine a PSBT with `this` previous txid.
+        this: Txid,
+        /// Into a PSBT with `that` previous txid.
+        that: Txid,
+    },
+    /// The spent output indices are not the same.
+    SpentOutputIndexMismatch {
+        /// Attempted to combine a PSBT with `this` spent output index.
+        this: u32,
+        /// Into a PSBT with `that` spent output index.
+        that: u32,
+    },
+    /// The same control block maps to different scripts in `tap_scripts`.
+    TaprootScriptConflict {
+        /// The control block with conflicting scripts.
+        control_block: ControlBlock,
+    },
+    /// The same key maps to different origins in `tap_key_origins`.
+    TaprootKeyOriginConflict {
+        /// The key with conflicting origins.
+        key: XOnlyPublicKey,
+    },
+    /// The same key has different signatures in `partial_sigs`.
+    SignatureConflict {
+        /// The key with conflicting signatures.
+        pubkey: PublicKey,
+    },
+    /// The inputs have different taproot signatures for the same key and leaf.
+    TaprootSignatureConflict {
+        /// The key with conflicting signatures, `None` for a key path signature if
+        /// `tap_internal_key` is not set.
+        key: Option<XOnlyPublicKey>,
+        /// The leaf hash of the conflicting script path signatures, `None` for the key path
+        /// signature (`tap_key_sig`).
+        leaf_hash: Option<TapLeafHash>,
+    },
+    /// Both inputs are finalized but with different final scripts.
+    FinalizedConflict,
+    /// The inputs have different witness UTXOs (value or script pubkey).
+    ///
+    /// The input index is available from the wrapping [`crate::CombineError::Input`].
+    WitnessUtxoMismatch,
+    /// The inputs have non-witness UTXOs with different txids.
+    ///
+    /// The input index is available from the wrapping [`crate::CombineError::Input`].
+    NonWitnessUtxoMismatch,
+    /// The inputs have different redeem or witness scripts.
+    ///
+    /// The input index is available from the wrapping [`crate::CombineError::Input`].
+    ScriptConflict {
+        /// The conflicting field.
+        which: ScriptField,
+    },
+    /// The inputs have different values for a required lock time field.
+    ///
+    /// The input index is available from the wrapping [`crate::CombineError::Input`].
+    LockTimeFieldConflict {
+        /// The conflicting field.
+        field: LockTimeField,
+    },
+    /// The inputs have different sequence numbers.
+    ///
+    /// The input index is available from the wrapping [`crate::CombineError::Input`].
+    SequenceConflict {
+        /// Attempted to combine a PSBT with `this` sequence number.
+        this: Sequence,
+        /// Into a PSBT with `that` sequence number.
+        that: Sequence,
+    },
+    /// The inputs have different sighash types.
+    ///
+    /// The input index is available from the wrapping [`crate::CombineError::Input`].
+    SighashTypeConflict {
+        /// Attempted to combine a PSBT with `this` sighash type.
+        this: PsbtSighashType,
+        /// Into a PSBT with `that` sighash type.
+        that: PsbtSighashType,
+    },
+}
+
+impl fmt::Display for CombineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use CombineError::*;
+
+        match *self {
+            PreviousTxidMismatch { ref this, ref that } => write!(
+                f,
+                "combine two PSBT inputs with different previous txids: {:?} {:?}",
+                this, that
+            ),
+            SpentOutputIndexMismatch { ref this, ref that } => write!(
+                f,
+                "combine two PSBT inputs with different spent output indices: {:?} {:?}",
+                this, that
+            ),
+            TaprootScriptConflict { ref control_block } => write!(
+                f,
+                "combine two PSBT inputs with conflicting tap scripts for control block: {:?}",
+                control_block
+            ),
+            TaprootKeyOriginConflict { ref key } => write!(
+                f,
+                "combine two PSBT inputs with conflicting tap key origins for key: {}",
+                key
+            ),
+            SignatureConflict { ref pubkey } => write!(
+                f,
+                "combine two PSBT inputs with conflicting signatures for key: {}",
+                pubkey
+            ),
+            TaprootSignatureConflict { ref key, ref leaf_hash } => write!(
+                f,
+                "combine two PSBT inputs with conflicting taproot signatures: key {:?} leaf {:?}",
+                key, leaf_hash
+            ),
+            FinalizedConflict =>
+                f.write_str("combine two finalized PSBT inputs with different final scripts"),
+            WitnessUtxoMismatch =>
+                f.write_str("combine two PSBT inputs with different witness UTXOs"),
+            NonWitnessUtxoMismatch =>
+                f.write_str("combine two PSBT inputs with different non-witness UTXOs"),
+            ScriptConflict { which } =>
+                write!(f, "combine two PSBT inputs with conflicting {} values", which),
+            LockTimeFieldConflict { field } =>
+                write!(f, "combine two PSBT inputs with conflicting {} values", field),
+            SequenceConflict { ref this, ref that } => write!(
+                f,
+                "combine two PSBT inputs with different sequence numbers: {} {}",
+                this, that
+            ),
+            SighashTypeConflict { ref this, ref that } =>
+                write!(f, "combine two PSBT inputs with different sighash types: {} {}", this, that),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for CombineError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use CombineError::*;
+
+        match *self {
+            PreviousTxidMismatch { .. }
+            | SpentOutputIndexMismatch { .. }
+            | TaprootScriptConflict { .. }
+            | TaprootKeyOriginConflict { .. }
+            | SignatureConflict { .. }
+            | TaprootSignatureConflict { .. }
+            | FinalizedConflict
+            | WitnessUtxoMismatch
+            | NonWitnessUtxoMismatch
+            | ScriptConflict { .. }
+            | LockTimeFieldConflict { .. }
+            | SequenceConflict { .. }
+            | SighashTypeConflict { .. } => None,
+        }
+    }
+}
+
+/// Error finalizing an input.
+#[cfg(feature = "miniscript")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FinalizeError {
+    /// The input spends a segwit output but the final script witness is empty.
+    EmptyWitness,
+}
+
+#[cfg(feature = "miniscript")]
+impl fmt::Display for FinalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use FinalizeError::*;
+
+        match *self {
+            EmptyWitness => f.write_str("finalize segwit input with an empty witness"),
+        }
+    }
+}
+
+#[cfg(all(feature = "miniscript", any(feature = "std", rust_v_1_81)))]
+impl StdError for FinalizeError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use FinalizeError::*;
+
+        match *self {
+            EmptyWitness => None,
+        }
+    }
 }
 
+/// The taproot leaf script is not in the tap tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TapScriptNotInTreeError;
+
+impl fmt::Display for TapScriptNotInTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("leaf script is not in the tap tree")
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for TapScriptNotInTreeError {}
+
 /// Asserts this input is valid as required for PSBT v2.
 // TODO: Upstream.
 pub(crate) fn assert_is_valid_v2(input: &bitcoin::psbt::Input) -> Result<(), V2InvalidError> {
@@ -457,9 +1470,9 @@ impl fmt::Display for V2InvalidError {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for V2InvalidError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for V2InvalidError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use V2InvalidError::*;
 
         match *self {
@@ -499,22 +1512,22 @@ pub enum V0InvalidError {
 
 impl fmt::Display for V0InvalidError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use IsValidPsbtV2Error::*;
+        use V0InvalidError::*;
 
         match *self {
             HasSequence =>
-                write!(f, "invalid v2 input, `sequence` should be excluded (PSBT_IN_SEQUENCE)"),
+                write!(f, "invalid v0 input, `sequence` should be excluded (PSBT_IN_SEQUENCE)"),
             HasMinTime =>
-                write!(f, "invalid v2 input, `min_time` should be excluded (PSBT_IN_REQUIRED_TIME_LOCKTIME)"),
+                write!(f, "invalid v0 input, `min_time` should be excluded (PSBT_IN_REQUIRED_TIME_LOCKTIME)"),
             HasMinHeight =>
-                write!(f, "invalid v2 input, `min_height` should be excluded (PSBT_IN_REQUIRED_HEIGHT_LOCKTIME)"),
+                write!(f, "invalid v0 input, `min_height` should be excluded (PSBT_IN_REQUIRED_HEIGHT_LOCKTIME)"),
         }
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for V0InvalidError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for V0InvalidError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use V0InvalidError::*;
 
         match *self {
@@ -522,3 +1535,278 @@ impl std::error::Error for V0InvalidError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::bip32::DerivationPath;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::{Amount, CompressedPublicKey};
+
+    use super::*;
+
+    fn pubkey(byte: u8) -> secp256k1::PublicKey {
+        let sk = SecretKey::from_slice(&[byte; 32]).expect("valid secret key");
+        secp256k1::PublicKey::from_secret_key(&Secp256k1::signing_only(), &sk)
+    }
+
+    fn key_source(child: u32) -> KeySource {
+        let path = format!("m/84'/0'/0'/0/{}", child);
+        let path = DerivationPath::from_str(&path).expect("valid derivation path");
+        (Fingerprint::from([0xd3, 0x4d, 0xb3, 0x3f]), path)
+    }
+
+    /// Returns an input spending a P2WPKH output locked to `pubkey(1)`.
+    fn input() -> Input {
+        let script_pubkey = ScriptBuf::new_p2wpkh(&CompressedPublicKey(pubkey(1)).wpubkey_hash());
+        let mut input = Input::new(&OutPoint::COINBASE_PREVOUT);
+        input.witness_utxo = Some(TxOut { value: Amount::from_sat(100_000), script_pubkey });
+        input
+    }
+
+    /// Returns a valid ECDSA signature, the signature does not need to verify.
+    fn ecdsa_signature(byte: u8) -> ecdsa::Signature {
+        let secp = Secp256k1::signing_only();
+        let sk = SecretKey::from_slice(&[1; 32]).expect("valid secret key");
+        let signature = secp.sign_ecdsa(&secp256k1::Message::from_digest([byte; 32]), &sk);
+        ecdsa::Signature { signature, sighash_type: EcdsaSighashType::All }
+    }
+
+    fn taproot_signature(byte: u8) -> taproot::Signature {
+        let signature = secp256k1::schnorr::Signature::from_slice(&[byte; 64]).expect("64 bytes");
+        taproot::Signature { signature, sighash_type: TapSighashType::Default }
+    }
+
+    fn finalized(witness_byte: u8) -> Input {
+        let mut input = input();
+        input.final_script_sig = Some(ScriptBuf::new());
+        input.final_script_witness =
+            Some(Witness::from_slice(&[vec![witness_byte; 72], pubkey(1).serialize().to_vec()]));
+        input
+    }
+
+    #[test]
+    fn combine_unions_bip32_derivation() {
+        let (pk_a, pk_b) = (pubkey(1), pubkey(2));
+
+        let mut a = input();
+        a.bip32_derivation.insert(pk_a, key_source(0));
+        let mut b = input();
+        b.bip32_derivation.insert(pk_b, key_source(1));
+
+        a.combine(b).expect("combine inputs");
+
+        assert_eq!(a.bip32_derivation.len(), 2);
+        assert_eq!(a.bip32_derivation.get(&pk_a), Some(&key_source(0)));
+        assert_eq!(a.bip32_derivation.get(&pk_b), Some(&key_source(1)));
+    }
+
+    #[test]
+    fn combine_one_finalized_one_not() {
+        let pk = PublicKey::new(pubkey(1));
+
+        let mut signed = input();
+        signed.partial_sigs.insert(pk, ecdsa_signature(1));
+        signed.tap_key_sig = Some(taproot_signature(1));
+        signed.bip32_derivation.insert(pubkey(1), key_source(0));
+        signed.sequence = Some(Sequence::ENABLE_RBF_NO_LOCKTIME);
+
+        // A conflicting signature on the finalized side is ignored, not reported.
+        let mut finalized = finalized(0x30);
+        finalized.partial_sigs.insert(pk, ecdsa_signature(2));
+        finalized.tap_key_sig = Some(taproot_signature(2));
+
+        let want = finalized.final_script_witness.clone();
+        for (mut this, that) in [(signed.clone(), finalized.clone()), (finalized, signed)] {
+            this.combine(that).expect("combine inputs");
+
+            assert!(this.is_finalized());
+            assert_eq!(this.final_script_sig, Some(ScriptBuf::new()));
+            assert_eq!(this.final_script_witness, want);
+            assert!(this.partial_sigs.is_empty());
+            assert!(this.tap_key_sig.is_none());
+            assert!(this.bip32_derivation.is_empty());
+            assert_eq!(this.sequence, Some(Sequence::ENABLE_RBF_NO_LOCKTIME));
+            assert!(this.witness_utxo.is_some());
+        }
+    }
+
+    #[test]
+    fn combine_finalized_with_empty_input_clears_signatures() {
+        let mut this = finalized(0x30);
+        this.partial_sigs.insert(PublicKey::new(pubkey(1)), ecdsa_signature(1));
+
+        this.combine(Input::new(&OutPoint::COINBASE_PREVOUT)).expect("combine inputs");
+
+        assert!(this.is_finalized());
+        assert!(this.partial_sigs.is_empty());
+    }
+
+    #[test]
+    fn combine_both_finalized_conflicting() {
+        let mut this = finalized(0x30);
+        let err = this.combine(finalized(0x31)).expect_err("conflicting final scripts");
+        assert_eq!(err, CombineError::FinalizedConflict);
+    }
+
+    #[test]
+    fn combine_both_finalized_same() {
+        let mut this = finalized(0x30);
+        this.combine(finalized(0x30)).expect("combine inputs");
+        assert_eq!(this, finalized(0x30));
+    }
+
+    #[test]
+    fn combine_conflicting_min_time() {
+        let time = |t| absolute::Time::from_consensus(t).expect("valid time");
+
+        let mut this = input();
+        this.min_time = Some(time(1_700_000_000));
+        let mut that = input();
+        that.min_time = Some(time(1_700_000_001));
+
+        let err = this.combine(that).expect_err("conflicting min_time");
+        let field = LockTimeField::MinTime;
+        assert_eq!(err, CombineError::LockTimeFieldConflict { field });
+    }
+
+    #[test]
+    fn combine_conflicting_min_height() {
+        let height = |h| absolute::Height::from_consensus(h).expect("valid height");
+
+        let mut this = input();
+        this.min_height = Some(height(800_000));
+        let mut that = input();
+        that.min_height = Some(height(800_001));
+
+        let err = this.combine(that).expect_err("conflicting min_height");
+        let field = LockTimeField::MinHeight;
+        assert_eq!(err, CombineError::LockTimeFieldConflict { field });
+    }
+
+    #[test]
+    fn combine_conflicting_scripts() {
+        let mut this = input();
+        this.redeem_script = Some(ScriptBuf::from_bytes(vec![0x51]));
+        let mut that = input();
+        that.redeem_script = Some(ScriptBuf::from_bytes(vec![0x52]));
+        let err = this.combine(that).expect_err("conflicting redeem_script");
+        assert_eq!(err, CombineError::ScriptConflict { which: ScriptField::Redeem });
+
+        let mut this = input();
+        this.witness_script = Some(ScriptBuf::from_bytes(vec![0x51]));
+        let mut that = input();
+        that.witness_script = Some(ScriptBuf::from_bytes(vec![0x52]));
+        let err = this.combine(that).expect_err("conflicting witness_script");
+        assert_eq!(err, CombineError::ScriptConflict { which: ScriptField::Witness });
+    }
+
+    #[test]
+    fn combine_conflicting_sequence() {
+        let mut this = input();
+        this.sequence = Some(Sequence::MAX);
+        let mut that = input();
+        that.sequence = Some(Sequence::ENABLE_RBF_NO_LOCKTIME);
+
+        let err = this.combine(that).expect_err("conflicting sequence");
+        assert_eq!(
+            err,
+            CombineError::SequenceConflict {
+                this: Sequence::MAX,
+                that: Sequence::ENABLE_RBF_NO_LOCKTIME
+            }
+        );
+    }
+
+    #[test]
+    fn combine_sighash_type() {
+        let all = PsbtSighashType::from(EcdsaSighashType::All);
+        let none = PsbtSighashType::from(EcdsaSighashType::None);
+
+        let mut this = input();
+        let mut that = input();
+        that.sighash_type = Some(all);
+        this.combine(that.clone()).expect("combine inputs");
+        assert_eq!(this.sighash_type, Some(all));
+
+        let mut this = input();
+        this.sighash_type = Some(none);
+        let err = this.combine(that).expect_err("conflicting sighash_type");
+        assert_eq!(err, CombineError::SighashTypeConflict { this: none, that: all });
+    }
+
+    /// Returns a pair of inputs spending `script_pubkey`, the first with only the witness UTXO and
+    /// the second with only the non-witness UTXO.
+    fn utxo_inputs(script_pubkey: ScriptBuf) -> (Input, Input) {
+        let utxo = TxOut { value: Amount::from_sat(100_000), script_pubkey };
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::EMPTY_COINBASE],
+            output: vec![utxo.clone()],
+        };
+        let outpoint = OutPoint { txid: tx.compute_txid(), vout: 0 };
+
+        let mut witness = Input::new(&outpoint);
+        witness.witness_utxo = Some(utxo);
+        let mut non_witness = Input::new(&outpoint);
+        non_witness.non_witness_utxo = Some(tx);
+        (witness, non_witness)
+    }
+
+    /// Combines the inputs in both orders, returning whether the witness and non-witness UTXOs
+    /// are kept.
+    fn combined_utxos(inputs: (Input, Input)) -> (bool, bool) {
+        let (a, b) = inputs;
+        let mut ab = a.clone();
+        ab.combine(b.clone()).expect("combine inputs");
+        let mut ba = b;
+        ba.combine(a).expect("combine inputs");
+
+        assert_eq!(ab, ba);
+        (ab.witness_utxo.is_some(), ab.non_witness_utxo.is_some())
+    }
+
+    #[test]
+    fn combine_p2wpkh_keeps_witness_utxo() {
+        let script_pubkey = ScriptBuf::new_p2wpkh(&CompressedPublicKey(pubkey(1)).wpubkey_hash());
+        assert_eq!(combined_utxos(utxo_inputs(script_pubkey)), (true, false));
+    }
+
+    #[test]
+    fn combine_p2sh_p2wpkh_keeps_witness_utxo() {
+        let redeem_script = ScriptBuf::new_p2wpkh(&CompressedPublicKey(pubkey(1)).wpubkey_hash());
+        let script_pubkey = ScriptBuf::new_p2sh(&redeem_script.script_hash());
+        let (mut witness, non_witness) = utxo_inputs(script_pubkey);
+        witness.redeem_script = Some(redeem_script);
+        assert_eq!(combined_utxos((witness, non_witness)), (true, false));
+    }
+
+    #[test]
+    fn combine_p2pkh_keeps_non_witness_utxo() {
+        let script_pubkey = ScriptBuf::new_p2pkh(&PublicKey::new(pubkey(1)).pubkey_hash());
+        assert_eq!(combined_utxos(utxo_inputs(script_pubkey)), (false, true));
+    }
+
+    #[test]
+    fn combine_p2tr_keeps_witness_utxo() {
+        let secp = Secp256k1::verification_only();
+        let (xonly, _) = pubkey(1).x_only_public_key();
+        let script_pubkey = ScriptBuf::new_p2tr(&secp, xonly, None);
+        assert_eq!(combined_utxos(utxo_inputs(script_pubkey)), (true, false));
+    }
+
+    #[test]
+    #[cfg(feature = "miniscript")]
+    fn finalize_clears_bip32_derivation() {
+        let mut input = input();
+        input.bip32_derivation.insert(pubkey(1), key_source(0));
+
+        let witness = Witness::from_slice(&[vec![0x30; 72], pubkey(1).serialize().to_vec()]);
+        let finalized = input.finalize(ScriptBuf::new(), witness).expect("finalize input");
+
+        assert!(finalized.is_finalized());
+        assert!(finalized.bip32_derivation.is_empty());
+    }
+}
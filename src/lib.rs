@@ -10,6 +10,7 @@
 #![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
 // Experimental features we need.
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
+#![cfg_attr(bench, feature(test))]
 // Coding conventions.
 #![warn(missing_docs)]
 #![doc(test(attr(warn(unused))))]
@@ -20,6 +21,9 @@
 
 extern crate alloc;
 
+#[cfg(bench)]
+extern crate test;
+
 /// Re-export of the `rust-bitcoin` crate.
 pub extern crate bitcoin;
 
@@ -27,34 +31,55 @@ pub extern crate bitcoin;
 #[macro_use]
 extern crate serde;
 
-mod error;
-mod input;
+// Macros must be declared before the modules that use them.
 #[macro_use]
 mod macros;
+mod diff;
+mod error;
+mod input;
 mod output;
+pub mod prelude;
 mod roles;
 #[cfg(feature = "serde")]
-mod serde_utils;
+pub mod serde_utils;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 
 use core::fmt;
 
-use bitcoin::bip32::{KeySource, Xpub};
+use bitcoin::bip32::{Fingerprint, KeySource, Xpub};
 use bitcoin::psbt::raw;
-use bitcoin::{absolute, transaction};
+#[cfg(feature = "miniscript")]
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::{
+    absolute, io, transaction, Amount, ScriptBuf, Sequence, Transaction, TxOut, Txid, Weight,
+};
 use bitcoin_internals::write_err;
 
-use crate::error::DetermineLockTimeError;
-use crate::prelude::BTreeMap;
+#[cfg(any(feature = "std", rust_v_1_81))]
+use crate::error::StdError;
+use crate::internal_prelude::{btree_map, BTreeMap, BTreeSet};
 
 #[rustfmt::skip]                // Keep public exports separate.
 #[doc(inline)]
 pub use self::{
-    input::Input,
+    error::{
+        AddInputError, BuildError, DetermineLockTimeError, FeeError, FundingUtxoError,
+        InconsistentKeySourcesError, IndexOutOfBoundsError, InputsNotModifiableError, LockTimeField,
+        MergeError, NonWitnessUtxoError, OutputsNotModifiableError, PsbtNotModifiableError,
+        RemoveError, ReplaceError, SanityError, ScriptField, TxVersionError, WeightError,
+        XpubInconsistency,
+    },
+    diff::{CombineReport, InputDiff, InputField, InputReport, PsbtDiff},
+    diff::{OutputDiff, OutputField, OutputReport},
+    input::{Input, InputBuilder, Musig2Key},
     output::Output,
-    roles::{Creator, Constructor, Updater, Signer, Extractor},
+    roles::{Creator, Constructor, Updater, Signer, Extractor, Session},
+    roles::{InputsOnlyModifiable, Mod, Modifiable, OutputsOnlyModifiable},
+    roles::{ExtractError, SighashError, SignError, SigningErrors, SigningKeys, UpdaterError},
 };
 #[cfg(feature = "miniscript")]
-pub use self::roles::Finalizer;
+pub use self::roles::{Finalizer, FinalizeExtractError, FinalizeMutError};
 
 /// The Inputs Modifiable Flag, set to 1 to indicate whether inputs can be added or removed.
 const INPUTS_MODIFIABLE: u8 = 0x01 << 0;
@@ -102,21 +127,40 @@ pub struct Psbt {
     pub xpub: BTreeMap<Xpub, KeySource>,
 
     /// The PSBT inputs.
+    ///
+    /// If mutated directly call [`Psbt::recompute_counts`] afterwards.
     pub inputs: Vec<Input>,
 
     /// The PSBT outputs.
+    ///
+    /// If mutated directly call [`Psbt::recompute_counts`] afterwards.
     pub outputs: Vec<Output>,
 }
 
 impl Psbt {
     /// Serialize PSBT as binary data.
-    pub fn serialize(&self) -> Vec<u8> { self.to_psbt().serialize() }
+    ///
+    /// The serialized input and output counts are the length of the inputs and outputs, the
+    /// `input_count` and `output_count` fields are not used.
+    pub fn serialize(&self) -> Vec<u8> { self.v2_psbt().serialize() }
+
+    /// Serialize PSBT as binary data in the version 0 format.
+    ///
+    /// Use this for interop with wallets that do not support PSBT version 2, [`Psbt::serialize`]
+    /// is the version 2 path. Some information is lost, see [`Psbt::to_psbt_v0`].
+    ///
+    /// Errors if the lock time can not be determined, since version 0 requires an `unsigned_tx`.
+    pub fn serialize_v0(&self) -> Result<Vec<u8>, DetermineLockTimeError> {
+        Ok(self.clone().to_psbt_v0()?.serialize())
+    }
 
     /// Serialize PSBT as a lowercase hex string.
-    pub fn serialize_hex(&self) -> String { self.to_psbt().serialize_hex() }
+    pub fn serialize_hex(&self) -> String { self.v2_psbt().serialize_hex() }
 
     /// Serialize the PSBT into a writer.
-    pub fn serialize_to_writer(&self, w: &mut impl Write) -> io::Result<usize> { self.to_psbt().serialize_to_writer(w) }
+    pub fn serialize_to_writer(&self, w: &mut impl io::Write) -> io::Result<usize> {
+        self.v2_psbt().serialize_to_writer(w)
+    }
 
     /// Deserialize PSBT from binary data.
     pub fn deserialize(mut bytes: &[u8]) -> Result<Self, DeserializeError> {
@@ -124,6 +168,27 @@ impl Psbt {
         Ok(Psbt::from_psbt(psbt)?)
     }
 
+    /// Deserialize PSBT from binary data, rejecting unknown and proprietary global fields.
+    ///
+    /// Same as [`Psbt::deserialize`] but stricter, for use in security sensitive contexts where
+    /// data that can not be verified should not be accepted. Note this crate's `Psbt` type does
+    /// not store global unknown or proprietary fields so the lenient `deserialize` drops them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DeserializeError::UnexpectedUnknownFields`] if the global unknown or proprietary
+    /// maps are non-empty.
+    pub fn deserialize_strict(bytes: &[u8]) -> Result<Self, DeserializeError> {
+        let psbt = bitcoin::psbt::Psbt::deserialize(bytes)?;
+        if !psbt.unknown.is_empty() || !psbt.proprietary.is_empty() {
+            return Err(DeserializeError::UnexpectedUnknownFields {
+                unknown: psbt.unknown.len(),
+                proprietary: psbt.proprietary.len(),
+            });
+        }
+        Ok(Psbt::from_psbt(psbt)?)
+    }
+
     // TODO: Implement Psbt::deserialize_hex function upstream.
     //
     // /// Deserialize PSBT from a hex string.
@@ -138,38 +203,103 @@ impl Psbt {
         Ok(Psbt::from_psbt(psbt)?)
     }
 
+    /// Deserialize a value from raw binary data read from a `BufRead` object, reading at most
+    /// `max_bytes` bytes.
+    ///
+    /// Use this when reading PSBTs from untrusted sources to bound memory usage.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DeserializeError::TooLarge`] if the reader contains more than `max_bytes` bytes.
+    pub fn deserialize_from_reader_with_limit<R: io::BufRead>(
+        r: &mut R,
+        max_bytes: usize,
+    ) -> Result<Self, DeserializeError> {
+        use io::Read as _;
+
+        // Read one extra byte so we can tell if the limit was exceeded.
+        let limit = (max_bytes as u64).saturating_add(1);
+        let mut buf = Vec::new();
+        r.take(limit).read_to_end(&mut buf).map_err(bitcoin::psbt::Error::from)?;
+
+        if buf.len() > max_bytes {
+            return Err(DeserializeError::TooLarge { max_bytes });
+        }
+        Self::deserialize(&buf)
+    }
+
     /// Converts a `rust-bitcoin` PSBT into this crates `Psbt` type.
     pub fn from_psbt(psbt: bitcoin::Psbt) -> Result<Psbt, InvalidError> {
         match psbt.version {
-            0 => Ok(Self::from_psbt_v0(psbt)?),
-            2 => Ok(Self::from_psbt_v2(psbt)?),
+            0 => Ok(Self::from_v0(psbt)?),
+            2 => Ok(Self::from_v2(psbt)?),
             other => Err(InvalidError::UnsupportedVersion(other)),
         }
     }
 
     /// Converts a `rust-bitcoin` PSBT into this crates `Psbt` type.
     fn from_v0(psbt: bitcoin::Psbt) -> Result<Psbt, V0InvalidError> {
-        assert_is_valid_v0(psbt)?;
-
-        let tx = psbt.unsigned_tx.unwrap();
+        let tx = psbt.unsigned_tx.ok_or(V0InvalidError::MissingUnsignedTx)?;
         let input_count = tx.input.len();
         let output_count = tx.output.len();
 
+        let inputs = psbt
+            .inputs
+            .into_iter()
+            .zip(tx.input.iter())
+            .enumerate()
+            .map(|(index, (input, txin))| {
+                let mut input = Input::from_v0(input, &txin.previous_output)
+                    .map_err(|e| V0InvalidError::InvalidInput(index, e))?;
+                input.sequence = Some(txin.sequence);
+                Ok(input)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let outputs = psbt
+            .outputs
+            .into_iter()
+            .zip(tx.output)
+            .enumerate()
+            .map(|(index, (output, txout))| {
+                Output::from_v0(output, txout).map_err(|e| V0InvalidError::InvalidOutput(index, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
         Ok(Psbt {
-            tx_version: transaction::Version::TWO, // TODO: Check this is correct.
-            fallback_lock_time: absolute::LockTime::ZERO,
+            tx_version: tx.version,
+            // A v0 PSBT has no per-input lock time requirements, only the lock time of the tx.
+            fallback_lock_time: tx.lock_time,
             input_count,
             output_count,
             tx_modifiable_flags: 0,
             xpub: psbt.xpub,
-            inputs: psbt.inputs.iter().map(|input| input.from_v0()),
-            outputs: psbt.outputs.iter().map(|output| output.from_v0())
+            inputs,
+            outputs,
         })
     }
 
     /// Converts a `rust-bitcoin` PSBT into this crates `Psbt` type.
     fn from_v2(psbt: bitcoin::Psbt) -> Result<Psbt, V2InvalidError> {
-        assert_is_valid_v2(psbt)?;
+        assert_is_valid_v2(&psbt)?;
+
+        let inputs = psbt
+            .inputs
+            .into_iter()
+            .enumerate()
+            .map(|(index, input)| {
+                Input::from_v2(input).map_err(|e| V2InvalidError::InvalidInput(index, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let outputs = psbt
+            .outputs
+            .into_iter()
+            .enumerate()
+            .map(|(index, output)| {
+                Output::from_v2(output).map_err(|e| V2InvalidError::InvalidOutput(index, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         Ok(Psbt {
             tx_version: psbt.tx_version.unwrap(),
@@ -178,8 +308,8 @@ impl Psbt {
             output_count: psbt.output_count.unwrap(),
             tx_modifiable_flags: psbt.tx_modifiable_flags.unwrap_or(0),
             xpub: psbt.xpub,
-            inputs: psbt.inputs.iter().map(|input| input.from_v2()),
-            outputs: psbt.outputs.iter().map(|output| output.from_v2()),
+            inputs,
+            outputs,
         })
     }
 
@@ -188,31 +318,39 @@ impl Psbt {
     /// # Returns
     ///
     /// A `bitcoin::Psbt` type with the correct fields to serialize as Version 2.
-    pub fn to_psbt(self) -> bitcoin::Psbt { self.to_psbt_v2() }
+    ///
+    /// # Errors
+    ///
+    /// If the PSBT fails the [`Psbt::sanity_check`].
+    pub fn to_psbt(self) -> Result<bitcoin::Psbt, SanityError> { self.to_psbt_v2() }
 
     /// Converts this crate's `Psbt` type to the `rust-bitcoin` one.
     ///
     /// # Returns
     ///
     /// A `bitcoin::Psbt` type with the correct fields to serialize as Version 0.
-    pub fn to_psbt_v0(self) -> bitcoin::Psbt {
+    ///
+    /// # Errors
+    ///
+    /// If the lock time cannot be determined, required to create the unsigned transaction.
+    pub fn to_psbt_v0(self) -> Result<bitcoin::Psbt, DetermineLockTimeError> {
         let version = 0;
-        let unsigned_tx = self.unsigned_tx();
+        let unsigned_tx = self.unsigned_tx()?;
 
-        bitcoin::Psbt {
+        Ok(bitcoin::Psbt {
             unsigned_tx: Some(unsigned_tx),
             xpub: self.xpub,
-            tx_version: self.tx_version,
+            tx_version: None,
             fallback_lock_time: None,
             input_count: None,
             output_count: None,
             tx_modifiable_flags: None,
             version,
-            proprietary: BTeeMap::default(),
-            unknown: BTeeMap::default(),
-            inputs: self.inputs.iter().map(|input| input.to_v0()),
-            outputs: self.outputs.iter().map(|output| output.to_v0())
-        }
+            proprietary: BTreeMap::default(),
+            unknown: BTreeMap::default(),
+            inputs: self.inputs.into_iter().map(|input| input.to_v0()).collect(),
+            outputs: self.outputs.into_iter().map(|output| output.to_v0()).collect(),
+        })
     }
 
     /// Converts this crate's `Psbt` type to the `rust-bitcoin` one.
@@ -220,23 +358,110 @@ impl Psbt {
     /// # Returns
     ///
     /// A `bitcoin::Psbt` type with the correct fields to serialize as Version 2.
-    pub fn to_psbt_v2(self) -> bitcoin::Psbt {
+    ///
+    /// # Errors
+    ///
+    /// If the PSBT fails the [`Psbt::sanity_check`], we never produce a PSBT whose declared input
+    /// and output counts are wrong.
+    pub fn to_psbt_v2(self) -> Result<bitcoin::Psbt, SanityError> {
+        self.sanity_check()?;
+        Ok(self.v2_psbt())
+    }
+
+    /// Creates a `rust-bitcoin` version 2 PSBT without consuming `self`.
+    ///
+    /// The input and output counts are taken from the length of the inputs and outputs.
+    fn v2_psbt(&self) -> bitcoin::Psbt {
         let version = 2;
 
         bitcoin::Psbt {
             unsigned_tx: None,
-            xpub: self.xpub,
-            tx_version: self.tx_version,
+            xpub: self.xpub.clone(),
+            tx_version: Some(self.tx_version),
             fallback_lock_time: Some(self.fallback_lock_time),
-            input_count: Some(self.input_count),
-            output_count: Some(self.output_count),
+            input_count: Some(self.inputs.len()),
+            output_count: Some(self.outputs.len()),
             tx_modifiable_flags: Some(self.tx_modifiable_flags),
             version,
-            proprietary: BTeeMap::default(),
-            unknown: BTeeMap::default(),
-            inputs: self.inputs.iter().map(|input| input.to_v2()),
-            outputs: self.outputs.iter().map(|output| output.to_v2())
+            proprietary: BTreeMap::default(),
+            unknown: BTreeMap::default(),
+            inputs: self.inputs.iter().cloned().map(Input::to_v2).collect(),
+            outputs: self.outputs.iter().cloned().map(Output::to_v2).collect(),
+        }
+    }
+
+    /// Validates this PSBT, checking everything required of a fully constructed PSBT.
+    ///
+    /// - The input and output counts are consistent (see [`Psbt::sanity_check`]).
+    /// - The lock time can be determined.
+    /// - No two inputs spend the same outpoint.
+    /// - If the SIGHASH_SINGLE flag is set, every input with a `SIGHASH_SINGLE` signature has a
+    ///   corresponding output at the same index.
+    fn validate(&self) -> Result<(), BuildError> {
+        self.sanity_check()?;
+        let _ = self.determine_lock_time()?;
+
+        if self.has_duplicate_inputs() {
+            return Err(BuildError::DuplicateInputs);
         }
+
+        if self.has_sighash_single() {
+            for (input_index, input) in self.inputs.iter().enumerate() {
+                if input.has_sighash_single_sig() && input_index >= self.outputs.len() {
+                    return Err(BuildError::SighashSinglePairing { input_index });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the input and output counts match the length of the inputs and outputs.
+    ///
+    /// The `input_count` and `output_count` fields are public and separate from the `inputs` and
+    /// `outputs` vectors so they can drift out of sync if the vectors are mutated directly. Use
+    /// [`Psbt::recompute_counts`] to re-sync them.
+    pub fn sanity_check(&self) -> Result<(), SanityError> {
+        if self.inputs.len() != self.input_count {
+            return Err(SanityError::InputCountMismatch {
+                count: self.input_count,
+                len: self.inputs.len(),
+            });
+        }
+        if self.outputs.len() != self.output_count {
+            return Err(SanityError::OutputCountMismatch {
+                count: self.output_count,
+                len: self.outputs.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Sets the input and output counts to the length of the inputs and outputs.
+    ///
+    /// Any direct mutation of the public `inputs` or `outputs` vectors should be followed by a call
+    /// to this function, [`Psbt::sanity_check`] can be used to detect counts that have drifted.
+    pub fn recompute_counts(&mut self) {
+        self.input_count = self.inputs.len();
+        self.output_count = self.outputs.len();
+    }
+
+    /// Calls `f` with a mutable reference to the inputs then updates the input count.
+    ///
+    /// Prefer this to mutating the public `inputs` field directly, the count is guaranteed to
+    /// match the inputs no matter what `f` does.
+    pub fn modify_inputs<F: FnOnce(&mut Vec<Input>)>(&mut self, f: F) {
+        f(&mut self.inputs);
+        self.input_count = self.inputs.len();
+    }
+
+    /// Calls `f` with a mutable reference to the outputs then updates the output count.
+    ///
+    /// Prefer this to mutating the public `outputs` field directly, the count is guaranteed to
+    /// match the outputs no matter what `f` does.
+    pub fn modify_outputs<F: FnOnce(&mut Vec<Output>)>(&mut self, f: F) {
+        f(&mut self.outputs);
+        self.output_count = self.outputs.len();
     }
 
     /// Combines this [`Psbt`] with `other` PSBT as described by BIP-174.
@@ -245,23 +470,13 @@ impl Psbt {
     ///
     /// This function is commutative `A.combine_with(B) = B.combine_with(A)`.
     ///
-    /// See [`combine()`] for a non-consuming version of this function.
+    /// See [`Psbt::combine`] for a version of this function that combines in place.
     pub fn combine_with(mut self, other: Self) -> Result<Psbt, CombineError> {
-        self.global.combine(other.global)?;
-
-        for (self_input, other_input) in self.inputs.iter_mut().zip(other.inputs.into_iter()) {
-            self_input.combine(other_input)?;
-        }
-
-        for (self_output, other_output) in self.outputs.iter_mut().zip(other.outputs.into_iter()) {
-            self_output.combine(other_output)?;
-        }
-
+        self.combine(other)?;
         Ok(self)
     }
 
-
-    /// Combines [`Global`] with `other`.
+    /// Combines this [`Psbt`] with `other` in place (as described by BIP-174).
     ///
     /// In accordance with BIP 174 this function is commutative i.e., `A.combine(B) == B.combine(A)`
     pub fn combine(&mut self, other: Self) -> Result<(), CombineError> {
@@ -273,20 +488,91 @@ impl Psbt {
             });
         }
 
-        // TODO: Check the bip, I just guessed these.
-        self.input_count += other.input_count;
-        self.output_count += other.output_count;
+        // Combining PSBTs that describe different transactions is not sensible, check this before
+        // zipping so that we do not silently ignore any extra inputs or outputs.
+        if self.inputs.len() != other.inputs.len() {
+            return Err(CombineError::InputCountMismatch {
+                this: self.inputs.len(),
+                that: other.inputs.len(),
+            });
+        }
+        if self.outputs.len() != other.outputs.len() {
+            return Err(CombineError::OutputCountMismatch {
+                this: self.outputs.len(),
+                that: other.outputs.len(),
+            });
+        }
 
-        // TODO: What to do about
-        // - fallback_lock_time
-        // - tx_modifiable_flags
+        // A SIGHASH_SINGLE signature commits to the output at the same index as its input so the
+        // input/output pairing must be identical in both PSBTs.
+        if self.has_sighash_single() || other.has_sighash_single() {
+            if let Some(index) = self.first_pairing_difference(&other) {
+                return Err(CombineError::SighashSinglePairingConflict { index });
+            }
+        }
+
+        // TODO: What to do about fallback_lock_time?
+
+        // To keep combine commutative the inputs/outputs modifiable flags are only kept if set in
+        // both PSBTs, the SIGHASH_SINGLE flag is sticky once set in either.
+        let both = self.tx_modifiable_flags & other.tx_modifiable_flags;
+        let either = self.tx_modifiable_flags | other.tx_modifiable_flags;
+        self.tx_modifiable_flags =
+            (both & (INPUTS_MODIFIABLE | OUTPUTS_MODIFIABLE)) | (either & SIGHASH_SINGLE);
 
         // BIP 174: The Combiner must remove any duplicate key-value pairs, in accordance with
         //          the specification. It can pick arbitrarily when conflicts occur.
 
         // Merging xpubs
-        for (xpub, (fingerprint1, derivation1)) in other.xpubs {
-            match self.xpubs.entry(xpub) {
+        self.extend_xpubs(other.xpub)?;
+
+        for (index, (self_input, other_input)) in
+            self.inputs.iter_mut().zip(other.inputs.into_iter()).enumerate()
+        {
+            self_input.combine(other_input).map_err(|error| CombineError::Input { index, error })?;
+        }
+
+        for (index, (self_output, other_output)) in
+            self.outputs.iter_mut().zip(other.outputs.into_iter()).enumerate()
+        {
+            self_output
+                .combine(other_output)
+                .map_err(|error| CombineError::Output { index, error })?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the first index at which the input/output pairs of `self` and `other` differ.
+    ///
+    /// Inputs are compared by outpoint and outputs by amount and script_pubkey.
+    fn first_pairing_difference(&self, other: &Psbt) -> Option<usize> {
+        let len = core::cmp::max(
+            core::cmp::max(self.inputs.len(), other.inputs.len()),
+            core::cmp::max(self.outputs.len(), other.outputs.len()),
+        );
+        let pair = |psbt: &Psbt, i: usize| {
+            let input = psbt.inputs.get(i).map(|i| (i.previous_txid, i.spent_output_index));
+            let output = psbt.outputs.get(i).map(|o| (o.amount, &o.script_pubkey));
+            (input, output)
+        };
+        (0..len).find(|i| pair(self, *i) != pair(other, *i))
+    }
+
+    /// Adds each xpub and key source in `iter` to the global xpubs.
+    ///
+    /// Conflicts with xpubs already in the PSBT are resolved the same way as in [`Psbt::combine`]
+    /// i.e., if one derivation path is a suffix of the other the longest derivation is kept.
+    ///
+    /// # Errors
+    ///
+    /// On the first xpub that has an inconsistent key source, entries before it have been added.
+    pub fn extend_xpubs<I: IntoIterator<Item = (Xpub, KeySource)>>(
+        &mut self,
+        iter: I,
+    ) -> Result<(), InconsistentKeySourcesError> {
+        for (xpub, (fingerprint1, derivation1)) in iter {
+            match self.xpub.entry(xpub) {
                 btree_map::Entry::Vacant(entry) => {
                     entry.insert((fingerprint1, derivation1));
                 }
@@ -301,33 +587,629 @@ impl Psbt {
                     // 3) choose longest derivation otherwise
 
                     let (fingerprint2, derivation2) = entry.get().clone();
-
-                    if (derivation1 == derivation2 && fingerprint1 == fingerprint2)
-                        || (derivation1.len() < derivation2.len()
-                            && derivation1[..]
-                                == derivation2[derivation2.len() - derivation1.len()..])
-                    {
+                    let (len1, len2) = (derivation1.len(), derivation2.len());
+
+                    let kind = if derivation1 == derivation2 {
+                        if fingerprint1 == fingerprint2 {
+                            continue;
+                        }
+                        XpubInconsistency::FingerprintMismatch
+                    } else if len1 == len2 {
+                        XpubInconsistency::PathLengthMismatch
+                    } else if len1 < len2 && derivation1[..] == derivation2[len2 - len1..] {
                         continue;
-                    } else if derivation2[..]
-                        == derivation1[derivation1.len() - derivation2.len()..]
-                    {
+                    } else if len2 < len1 && derivation2[..] == derivation1[len1 - len2..] {
                         entry.insert((fingerprint1, derivation1));
                         continue;
-                    }
-                    return Err(InconsistentKeySourcesError(xpub).into());
+                    } else {
+                        XpubInconsistency::PathNotSuffix
+                    };
+                    return Err(InconsistentKeySourcesError { xpub, kind });
                 }
             }
         }
+        Ok(())
+    }
 
+    /// Appends the inputs and outputs of `other` onto this PSBT.
+    ///
+    /// This is distinct from [`Psbt::combine`], which merges two PSBTs describing the _same_
+    /// transaction, this function builds a larger transaction from two PSBTs. Only inputs and
+    /// outputs are merged, all global fields of `other` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// If either PSBT does not have the inputs modifiable flag set, or if `other` has outputs and
+    /// either PSBT does not have the outputs modifiable flag set.
+    pub fn merge_inputs_from(&mut self, other: Psbt) -> Result<(), MergeError> {
+        if !self.is_inputs_modifiable() {
+            return Err(MergeError::NotModifiable(InputsNotModifiableError.into()));
+        }
+        if !other.is_inputs_modifiable() {
+            return Err(MergeError::OtherNotModifiable(InputsNotModifiableError.into()));
+        }
+        if !other.outputs.is_empty() {
+            if !self.is_outputs_modifiable() {
+                return Err(MergeError::NotModifiable(OutputsNotModifiableError.into()));
+            }
+            if !other.is_outputs_modifiable() {
+                return Err(MergeError::OtherNotModifiable(OutputsNotModifiableError.into()));
+            }
+        }
+
+        self.inputs.extend(other.inputs);
+        self.outputs.extend(other.outputs);
+        self.recompute_counts();
+
+        Ok(())
+    }
+
+    /// Copies the finalized scripts of each input of `other` into this PSBT.
+    ///
+    /// For each input, `final_script_sig` and `final_script_witness` are copied from `other` if
+    /// they are set in `other` and not in this PSBT, no other fields are changed. This is narrower
+    /// than [`Psbt::combine`] and is intended for collecting inputs finalized by different parties.
+    ///
+    /// # Errors
+    ///
+    /// If the PSBTs have a different number of inputs or if the inputs at the same index do not
+    /// spend the same outpoint. Nothing is copied if an error is returned.
+    pub fn merge_finalized_from(&mut self, other: &Psbt) -> Result<(), MergeError> {
+        if self.inputs.len() != other.inputs.len() {
+            return Err(MergeError::InputCountMismatch {
+                this: self.inputs.len(),
+                that: other.inputs.len(),
+            });
+        }
+        for (index, (this, that)) in self.inputs.iter().zip(other.inputs.iter()).enumerate() {
+            if this.previous_txid != that.previous_txid
+                || this.spent_output_index != that.spent_output_index
+            {
+                return Err(MergeError::OutpointMismatch { index });
+            }
+        }
+
+        for (this, that) in self.inputs.iter_mut().zip(other.inputs.iter()) {
+            if this.final_script_sig.is_none() {
+                this.final_script_sig = that.final_script_sig.clone();
+            }
+            if this.final_script_witness.is_none() {
+                this.final_script_witness = that.final_script_witness.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the transaction version.
+    ///
+    /// The `tx_version` field is public and may be set directly, this function is the checked
+    /// alternative. Version 0 is rejected, other non-standard versions are accepted however
+    /// transactions using them will not be relayed by default, see
+    /// [`transaction::Version::is_standard`].
+    pub fn set_tx_version(&mut self, version: transaction::Version) -> Result<(), TxVersionError> {
+        if version.0 == 0 {
+            return Err(TxVersionError);
+        }
+        self.tx_version = version;
         Ok(())
     }
-    
+
+    /// Returns an iterator over the inputs paired with their funding UTXO.
+    pub fn input_pairs(
+        &self,
+    ) -> impl Iterator<Item = Result<(&Input, &TxOut), FundingUtxoError>> + '_ {
+        self.inputs.iter().map(|input| input.funding_utxo().map(|utxo| (input, utxo)))
+    }
+
+    /// Calculates the fee for this PSBT.
+    ///
+    /// The fee is the sum of the funding UTXO amounts minus the sum of the output amounts.
+    pub fn fee(&self) -> Result<Amount, FeeError> {
+        let input_amount = self.total_input_amount()?;
+        let output_amount = self.total_output_amount()?;
+
+        input_amount
+            .checked_sub(output_amount)
+            .ok_or_else(|| negative_fee(input_amount, output_amount))
+    }
+
+    /// Checks that adding `output` would not make the output amounts exceed the input amounts.
+    ///
+    /// Requires all inputs to have a funding UTXO.
+    pub(crate) fn check_output_affordable(&self, output: &Output) -> Result<(), FeeError> {
+        let input_amount = self.total_input_amount()?;
+        let output_amount = checked_add_amounts(self.total_output_amount()?, output.amount)?;
+
+        if output_amount > input_amount {
+            return Err(negative_fee(input_amount, output_amount));
+        }
+        Ok(())
+    }
+
+    /// Returns the sum of the funding UTXO amounts of all inputs.
+    fn total_input_amount(&self) -> Result<Amount, FeeError> {
+        let mut input_amount = Amount::ZERO;
+        for pair in self.input_pairs() {
+            let (_, utxo) = pair?;
+            input_amount = checked_add_amounts(input_amount, utxo.value)?;
+        }
+        Ok(input_amount)
+    }
+
+    /// Returns the sum of the amounts of all outputs.
+    fn total_output_amount(&self) -> Result<Amount, FeeError> {
+        let mut output_amount = Amount::ZERO;
+        for output in &self.outputs {
+            output_amount = checked_add_amounts(output_amount, output.amount)?;
+        }
+        Ok(output_amount)
+    }
+
+    /// Finalizes this PSBT and extracts the fully signed transaction.
+    ///
+    /// A shortcut for the happy path of a fully signed PSBT, equivalent to using the [`Finalizer`]
+    /// and [`Extractor`] roles in turn. Use the roles directly if they are separate entities.
+    #[cfg(feature = "miniscript")]
+    pub fn finalize_and_extract<C: Verification>(
+        self,
+        secp: &Secp256k1<C>,
+    ) -> Result<Transaction, FinalizeExtractError> {
+        let psbt = Finalizer::new(self)?.finalize_mut(secp)?;
+        Ok(Extractor::new(psbt)?.extract_tx()?)
+    }
+
+    /// Removes redundant data from this PSBT to reduce its size before sending it elsewhere.
+    ///
+    /// For each input:
+    ///
+    /// - If the input has both UTXOs the redundant one is removed, segwit inputs keep the
+    ///   `witness_utxo` and legacy inputs keep the `non_witness_utxo` (as for [`Psbt::combine`]).
+    /// - If the input is finalized all signing data is removed i.e., the partial signatures,
+    ///   sighash type, redeem and witness scripts, BIP-32 derivations, hash preimages, and all
+    ///   taproot fields. This is the same data a finalizer clears when finalizing.
+    ///
+    /// The funding UTXO, sequence number, lock time requirements, and proprietary and unknown
+    /// fields are always kept so the stripped PSBT remains valid and describes the same
+    /// transaction.
+    pub fn strip_for_transport(&mut self) {
+        for input in self.inputs.iter_mut() {
+            input.strip_for_transport();
+        }
+    }
+
+    /// Returns an iterator over the `script_pubkey` of each output.
+    pub fn output_script_pubkeys(&self) -> impl Iterator<Item = &ScriptBuf> {
+        self.outputs.iter().map(|output| &output.script_pubkey)
+    }
+
+    /// Returns an iterator over the `script_pubkey` of each input's funding UTXO.
+    ///
+    /// See [`Input::funding_utxo`].
+    pub fn input_funding_scripts(
+        &self,
+    ) -> impl Iterator<Item = Result<&ScriptBuf, FundingUtxoError>> {
+        self.inputs.iter().map(|input| input.funding_utxo().map(|utxo| &utxo.script_pubkey))
+    }
+
+    /// Returns true if the input at `index` requires a `non_witness_utxo` to be signed.
+    ///
+    /// This is the case when the input's funding script is legacy (non-segwit). Segwit inputs may
+    /// use just a `witness_utxo`, however note that some signers (e.g. hardware wallets) also
+    /// require the `non_witness_utxo` for segwit v0 inputs to protect against fee attacks.
+    ///
+    /// A P2SH funding script is considered segwit if the input's `redeem_script` is a witness
+    /// program, if the `redeem_script` is not set the input is assumed to be legacy.
+    ///
+    /// # Errors
+    ///
+    /// If `index` is out of bounds or if the input has no funding UTXO, since the funding script
+    /// is then unknown.
+    pub fn requires_non_witness_utxo(&self, index: usize) -> Result<bool, NonWitnessUtxoError> {
+        Ok(self.checked_input(index)?.witness_version()?.is_none())
+    }
+
+    /// Classifies this PSBT by whether its inputs spend segwit outputs.
+    ///
+    /// P2SH wrapped segwit inputs are considered segwit (see [`Input::witness_version`]). A PSBT
+    /// with no inputs is classified as [`SegwitClass::AllLegacy`], like a transaction with no
+    /// segwit inputs it serializes without witness data.
+    ///
+    /// # Errors
+    ///
+    /// If any input has no funding UTXO, since the funding script is then unknown.
+    pub fn segwit_classification(&self) -> Result<SegwitClass, FundingUtxoError> {
+        let mut segwit = 0;
+        for input in &self.inputs {
+            if input.witness_version()?.is_some() {
+                segwit += 1;
+            }
+        }
+
+        Ok(match segwit {
+            0 => SegwitClass::AllLegacy,
+            n if n == self.inputs.len() => SegwitClass::AllSegwit,
+            _ => SegwitClass::Mixed,
+        })
+    }
+
+    /// Returns the number of inputs of each script type, classified by funding script pubkey.
+    ///
+    /// P2SH wrapped segwit inputs are counted as `p2sh`. Inputs spending a segwit version other
+    /// than 0 or 1 are not counted.
+    ///
+    /// # Errors
+    ///
+    /// If any input has no funding UTXO, since the funding script is then unknown.
+    pub fn count_by_type(&self) -> Result<ScriptTypeCounts, FundingUtxoError> {
+        let mut counts = ScriptTypeCounts::default();
+        for input in &self.inputs {
+            let spk = &input.funding_utxo()?.script_pubkey;
+            if spk.is_p2tr() {
+                counts.taproot += 1;
+            } else if spk.is_p2wpkh() || spk.is_p2wsh() {
+                counts.segwit_v0 += 1;
+            } else if spk.is_p2sh() {
+                counts.p2sh += 1;
+            } else if !spk.is_witness_program() {
+                counts.legacy += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Returns an iterator over the outputs, and their indices, that pay to `spk`.
+    pub fn outputs_paying_to<'a>(
+        &'a self,
+        spk: &'a ScriptBuf,
+    ) -> impl Iterator<Item = (usize, &'a Output)> {
+        self.outputs.iter().enumerate().filter(move |(_, output)| output.script_pubkey == *spk)
+    }
+
+    /// Returns the sum of the amounts of all outputs that pay to `spk`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FeeError::Overflow`] if the sum overflows or exceeds [`Amount::MAX_MONEY`].
+    pub fn total_paid_to(&self, spk: &ScriptBuf) -> Result<Amount, FeeError> {
+        let mut total = Amount::ZERO;
+        for (_, output) in self.outputs_paying_to(spk) {
+            total = checked_add_amounts(total, output.amount)?;
+        }
+        Ok(total)
+    }
+
+    /// Returns the estimated weight contributed by each input, in input order.
+    ///
+    /// Finalized inputs use the actual final scriptSig and scriptWitness, for other inputs the
+    /// weight is estimated from the funding script type assuming a single key spend.
+    pub fn input_weights(&self) -> Result<Vec<Weight>, WeightError> {
+        self.inputs
+            .iter()
+            .enumerate()
+            .map(|(input_index, input)| {
+                input
+                    .estimated_weight()
+                    .map_err(|error| WeightError::FundingUtxo { input_index, error })?
+                    .ok_or(WeightError::UnsupportedScript { input_index })
+            })
+            .collect()
+    }
+
+    /// Returns the estimated virtual size, in vbytes, of the final transaction.
+    ///
+    /// This is `ceil(weight / 4)` as used by the mempool when calculating fee rates. Finalized
+    /// inputs use the actual final scriptSig and scriptWitness, for other inputs a conservative
+    /// upper bound is used based on the funding script type (see [`Psbt::input_weights`]).
+    ///
+    /// # Errors
+    ///
+    /// If the weight of any input can not be estimated.
+    pub fn estimated_vsize(&self) -> Result<usize, WeightError> {
+        // Version (4 bytes) and lock time (4 bytes).
+        const BASE_SIZE: u64 = 8;
+
+        let input_weight = self.input_weights()?.into_iter().map(|w| w.to_wu()).sum::<u64>();
+        let output_size: u64 = self
+            .outputs
+            .iter()
+            .map(|output| {
+                let len = output.script_pubkey.len();
+                // Amount (8 bytes) and length prefixed script pubkey.
+                8 + input::compact_size_len(len) + len as u64
+            })
+            .sum();
+        let base_size = BASE_SIZE
+            + input::compact_size_len(self.inputs.len())
+            + input::compact_size_len(self.outputs.len())
+            + output_size;
+        // The segwit marker and flag bytes, plus an empty witness for each non-segwit input. The
+        // Finalizer clears the `redeem_script` so finalized inputs are classified by their witness.
+        let is_segwit = |input: &Input| {
+            if input.is_finalized() {
+                input.final_script_witness.as_ref().map_or(false, |witness| !witness.is_empty())
+            } else {
+                input.spends_segwit()
+            }
+        };
+        let non_segwit = self.inputs.iter().filter(|input| !is_segwit(input)).count() as u64;
+        let segwit = if non_segwit < self.inputs.len() as u64 { 2 + non_segwit } else { 0 };
+
+        let weight = base_size * 4 + segwit + input_weight;
+        Ok(((weight + 3) / 4) as usize)
+    }
+
+    /// Returns true if `self` and `other` are semantically equal.
+    ///
+    /// Two PSBTs are semantically equal if they describe the same unsigned transaction (i.e, have
+    /// the same [`Psbt::id`]), spend the same funding UTXOs, and have the same signature data
+    /// (ECDSA and taproot signatures and final scripts). Other fields are ignored, in particular an
+    /// input that has a `non_witness_utxo` is equal to one with only the matching `witness_utxo`.
+    ///
+    /// Returns false if the lock time or any funding UTXO of either PSBT cannot be determined.
+    pub fn semantic_eq(&self, other: &Psbt) -> bool {
+        match (self.id(), other.id()) {
+            (Ok(a), Ok(b)) if a == b => {}
+            _ => return false,
+        }
+
+        self.inputs.iter().zip(other.inputs.iter()).all(|(a, b)| {
+            let same_utxo = match (a.funding_utxo(), b.funding_utxo()) {
+                (Ok(x), Ok(y)) => x == y,
+                _ => false,
+            };
+            same_utxo
+                && a.partial_sigs == b.partial_sigs
+                && a.tap_key_sig == b.tap_key_sig
+                && a.tap_script_sigs == b.tap_script_sigs
+                && a.final_script_sig == b.final_script_sig
+                && a.final_script_witness == b.final_script_witness
+        })
+    }
+
+    /// Returns a summary of the keys in this PSBT sourced from master key `fp`.
+    ///
+    /// Counts the inputs and outputs that have a key sourced from `fp` in either of their
+    /// `bip32_derivation` or `tap_key_origins` maps, along with the number of distinct keys.
+    pub fn keys_from_fingerprint(&self, fp: Fingerprint) -> KeyStats {
+        let mut ecdsa_keys = BTreeSet::new();
+        let mut x_only_keys = BTreeSet::new();
+        let mut stats = KeyStats::default();
+
+        for input in &self.inputs {
+            let mut found = false;
+            for (pk, _) in input.keys_for_fingerprint(fp) {
+                ecdsa_keys.insert(*pk);
+                found = true;
+            }
+            for (xonly, _) in input.tap_keys_for_fingerprint(fp) {
+                x_only_keys.insert(*xonly);
+                found = true;
+            }
+            if found {
+                stats.inputs += 1;
+            }
+        }
+
+        for output in &self.outputs {
+            let mut found = false;
+            for (pk, _) in output.bip32_derivation.iter().filter(|(_, (f, _))| *f == fp) {
+                ecdsa_keys.insert(*pk);
+                found = true;
+            }
+            for (xonly, _) in output.tap_key_origins.iter().filter(|(_, (_, (f, _)))| *f == fp) {
+                x_only_keys.insert(*xonly);
+                found = true;
+            }
+            if found {
+                stats.outputs += 1;
+            }
+        }
+
+        stats.keys = ecdsa_keys.len() + x_only_keys.len();
+        stats
+    }
+
+    /// Clears the `final_script_sig` and `final_script_witness` of every input.
+    ///
+    /// Use this to revert a premature finalization, all other fields (including `partial_sigs`
+    /// and the taproot signatures) are left intact so the PSBT can be re-finalized.
+    ///
+    /// Note this does not restore any data removed during finalization. BIP-174 recommends that
+    /// finalizers clear all other input fields (as the `Finalizer` in this crate does) so this is
+    /// only fully reversible if the finalizer preserved the signatures and other signing data.
+    pub fn unfinalize(&mut self) {
+        for input in self.inputs.iter_mut() {
+            input.final_script_sig = None;
+            input.final_script_witness = None;
+        }
+    }
+
+    /// Returns the number of signatures across all inputs.
+    ///
+    /// This is the number of `partial_sigs` and `tap_script_sigs` entries, plus one for each
+    /// `tap_key_sig`. Useful as a progress metric along with [`Psbt::required_signature_count`].
+    pub fn signature_count(&self) -> usize {
+        self.inputs
+            .iter()
+            .map(|input| {
+                input.partial_sigs.len()
+                    + input.tap_script_sigs.len()
+                    + usize::from(input.tap_key_sig.is_some())
+            })
+            .sum()
+    }
+
+    /// Returns the number of signatures implied by the keys declared across all inputs.
+    ///
+    /// Each `bip32_derivation` entry requires one signature. Each `tap_key_origins` entry requires
+    /// one signature per leaf hash, plus a key path signature if it is the internal key.
+    ///
+    /// Note this is best-effort, the PSBT does not always declare the full signing policy so not
+    /// all declared keys may need to sign (e.g. a threshold multisig or a taproot input that will
+    /// be spent via a single path).
+    pub fn required_signature_count(&self) -> usize {
+        self.inputs
+            .iter()
+            .map(|input| {
+                let taproot: usize = input
+                    .tap_key_origins
+                    .iter()
+                    .map(|(xonly, (leaf_hashes, _))| {
+                        leaf_hashes.len() + usize::from(input.tap_internal_key == Some(*xonly))
+                    })
+                    .sum();
+                input.bip32_derivation.len() + taproot
+            })
+            .sum()
+    }
+
+    /// Returns true if two or more inputs spend the same outpoint.
+    ///
+    /// BIP-370 forbids spending the same outpoint twice however [`Constructor::input`] does not
+    /// check for duplicates, use this to validate a PSBT before signing.
+    pub fn has_duplicate_inputs(&self) -> bool {
+        let mut seen = BTreeSet::new();
+        !self
+            .inputs
+            .iter()
+            .all(|input| seen.insert((input.previous_txid, input.spent_output_index)))
+    }
+
+    /// Checks that `input` can be added to this PSBT.
+    ///
+    /// The input must not spend the same outpoint as an existing input and, if it has a
+    /// `non_witness_utxo`, the `spent_output_index` must be in bounds.
+    pub(crate) fn check_new_input(&self, input: &Input) -> Result<(), AddInputError> {
+        let (previous_txid, spent_output_index) = (input.previous_txid, input.spent_output_index);
+        if self.inputs.iter().any(|i| {
+            i.previous_txid == previous_txid && i.spent_output_index == spent_output_index
+        }) {
+            return Err(AddInputError::DuplicateInput { previous_txid, spent_output_index });
+        }
+
+        if let Some(ref tx) = input.non_witness_utxo {
+            let vout = spent_output_index as usize;
+            if vout >= tx.output.len() {
+                return Err(FundingUtxoError::OutOfBounds { vout, len: tx.output.len() }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the inputs that prevent the lock time from being determined.
+    ///
+    /// If some inputs can only be satisfied by a time based lock time and others only by a height
+    /// based lock time returns `Some((time_based, height_based))` with the indices of each set of
+    /// inputs, otherwise returns `None`.
+    pub fn conflicting_lock_time_inputs(&self) -> Option<(Vec<usize>, Vec<usize>)> {
+        let mut time_based = Vec::new();
+        let mut height_based = Vec::new();
+
+        for (i, input) in self.inputs.iter().enumerate() {
+            if input.requires_time_based_lock_time() {
+                time_based.push(i);
+            }
+            if input.requires_height_based_lock_time() {
+                height_based.push(i);
+            }
+        }
+
+        if time_based.is_empty() || height_based.is_empty() {
+            None
+        } else {
+            Some((time_based, height_based))
+        }
+    }
+
+    /// Replaces the input at `index` with `input`, returning the old input.
+    ///
+    /// Useful for RBF style edits e.g., swapping a coin that has since been spent. Any signatures
+    /// on `input` are cleared since they are not valid for this transaction.
+    ///
+    /// # Errors
+    ///
+    /// If the inputs modifiable flag is not set or `index` is out of bounds.
+    pub fn replace_input(&mut self, index: usize, mut input: Input) -> Result<Input, ReplaceError> {
+        if !self.is_inputs_modifiable() {
+            return Err(InputsNotModifiableError.into());
+        }
+        let slot = self.checked_input_mut(index)?;
+        input.clear_signatures();
+        Ok(core::mem::replace(slot, input))
+    }
+
+    /// Removes the input at `index`, replacing it with the last input, and returns it.
+    ///
+    /// This is O(1) but does not preserve the ordering of the inputs, see [`Vec::swap_remove`].
+    ///
+    /// # Errors
+    ///
+    /// If the inputs modifiable flag is not set, if the SIGHASH_SINGLE flag is set (since
+    /// reordering the inputs would break the input/output pairing), or if `index` is out of bounds.
+    pub fn swap_remove_input(&mut self, index: usize) -> Result<Input, RemoveError> {
+        if !self.is_inputs_modifiable() {
+            return Err(InputsNotModifiableError.into());
+        }
+        if self.has_sighash_single() {
+            return Err(RemoveError::SighashSingle);
+        }
+        let _ = self.checked_input(index)?;
+
+        let input = self.inputs.swap_remove(index);
+        self.input_count = self.inputs.len();
+        Ok(input)
+    }
+
+    /// Sets the sequence number for the input at `index`.
+    ///
+    /// This is equivalent to [`Updater::set_sequence`] but does not require wrapping the PSBT in
+    /// an `Updater`. If `seq` differs from the current sequence number all signatures on the input
+    /// are cleared (see [`Input::clear_signatures`]).
+    ///
+    /// Note this function does not validate the interaction between `seq` and any lock time
+    /// requirements of the input (i.e. BIP-68 relative lock time semantics or enabling of the
+    /// absolute lock time), it is up to the caller to ensure the sequence number is sensible.
+    pub fn set_input_sequence(
+        &mut self,
+        index: usize,
+        seq: Sequence,
+    ) -> Result<(), IndexOutOfBoundsError> {
+        let input = self.checked_input_mut(index)?;
+        if input.sequence.unwrap_or(Sequence::MAX) != seq {
+            input.clear_signatures();
+        }
+        input.sequence = Some(seq);
+        Ok(())
+    }
+
+    /// Returns a reference to the input at `index`, or `None` if it does not exist.
+    pub fn input(&self, index: usize) -> Option<&Input> { self.inputs.get(index) }
+
+    /// Returns a reference to the output at `index`, or `None` if it does not exist.
+    pub fn output(&self, index: usize) -> Option<&Output> { self.outputs.get(index) }
+
+    /// Returns the inputs of this PSBT.
+    pub fn inputs(&self) -> &[Input] { &self.inputs }
+
+    /// Returns the outputs of this PSBT.
+    pub fn outputs(&self) -> &[Output] { &self.outputs }
+
+    /// Returns a reference to the input at `index`, or an error if it does not exist.
+    fn checked_input(&self, index: usize) -> Result<&Input, IndexOutOfBoundsError> {
+        let length = self.inputs.len();
+        self.inputs.get(index).ok_or(IndexOutOfBoundsError::Inputs { index, length })
+    }
+
+    /// Returns a mutable reference to the input at `index`, or an error if it does not exist.
+    fn checked_input_mut(&mut self, index: usize) -> Result<&mut Input, IndexOutOfBoundsError> {
+        let length = self.inputs.len();
+        self.inputs.get_mut(index).ok_or(IndexOutOfBoundsError::Inputs { index, length })
+    }
+
     fn set_inputs_modifiable_flag(&mut self) { self.tx_modifiable_flags |= INPUTS_MODIFIABLE; }
 
     fn set_outputs_modifiable_flag(&mut self) { self.tx_modifiable_flags |= OUTPUTS_MODIFIABLE; }
 
-    // TODO: Handle SIGHASH_SINGLE correctly.
-    #[allow(dead_code)]
     fn set_sighash_single_flag(&mut self) { self.tx_modifiable_flags |= SIGHASH_SINGLE; }
 
     fn clear_inputs_modifiable_flag(&mut self) { self.tx_modifiable_flags &= !INPUTS_MODIFIABLE; }
@@ -338,13 +1220,47 @@ impl Psbt {
     #[allow(dead_code)]
     fn clear_sighash_single_flag(&mut self) { self.tx_modifiable_flags &= !SIGHASH_SINGLE; }
 
-    fn is_inputs_modifiable(&self) -> bool { self.tx_modifiable_flags & INPUTS_MODIFIABLE > 0 }
+    /// Updates the transaction modifiable flags after a signature using `sighash` is added.
+    ///
+    /// As specified by BIP-370:
+    ///
+    /// - If `sighash` does not have `SIGHASH_ANYONECANPAY` set the Inputs Modifiable Flag is
+    ///   cleared.
+    /// - If `sighash` is not `SIGHASH_NONE` or `SIGHASH_SINGLE` the Outputs Modifiable Flag is
+    ///   cleared.
+    /// - If `sighash` is `SIGHASH_SINGLE` the Has SIGHASH_SINGLE flag is set.
+    ///
+    /// `sighash` is the raw sighash byte, the taproot `SIGHASH_DEFAULT` (0x00) is treated as
+    /// `SIGHASH_ALL`.
+    pub fn clear_tx_modifiable(&mut self, sighash: u8) {
+        const ANYONECANPAY: u8 = 0x80;
+        const NONE: u8 = 0x02;
+        const SINGLE: u8 = 0x03;
+
+        if sighash & ANYONECANPAY == 0 {
+            self.clear_inputs_modifiable_flag();
+        }
+
+        match sighash & 0x1f {
+            NONE => {}
+            SINGLE => self.set_sighash_single_flag(),
+            _ => self.clear_outputs_modifiable_flag(),
+        }
+    }
 
-    fn is_outputs_modifiable(&self) -> bool { self.tx_modifiable_flags & OUTPUTS_MODIFIABLE > 0 }
+    /// Returns true if the Inputs Modifiable Flag is set i.e., inputs can be added or removed.
+    pub fn is_inputs_modifiable(&self) -> bool { self.tx_modifiable_flags & INPUTS_MODIFIABLE > 0 }
 
-    // TODO: Investigate if we should be using this function?
-    #[allow(dead_code)]
-    fn has_sighash_single(&self) -> bool { self.tx_modifiable_flags & SIGHASH_SINGLE > 0 }
+    /// Returns true if the Outputs Modifiable Flag is set i.e., outputs can be added or removed.
+    pub fn is_outputs_modifiable(&self) -> bool {
+        self.tx_modifiable_flags & OUTPUTS_MODIFIABLE > 0
+    }
+
+    /// Returns true if the Has SIGHASH_SINGLE flag is set.
+    ///
+    /// If set, the transaction has a `SIGHASH_SINGLE` signature whose input and output pairing
+    /// must be preserved.
+    pub fn has_sighash_single(&self) -> bool { self.tx_modifiable_flags & SIGHASH_SINGLE > 0 }
 
     /// Returns this PSBT's unique identification.
     fn id(&self) -> Result<Txid, DetermineLockTimeError> {
@@ -372,42 +1288,43 @@ impl Psbt {
 
     /// Determines the lock time as specified in [BIP-370] if it is possible to do so.
     ///
+    /// Every role constructor calls this so it is done in a single pass over the inputs. The
+    /// result is not cached, it depends on the lock time fields of the inputs which are public and
+    /// can be mutated at any time.
+    ///
     /// [BIP-370]: <https://github.com/bitcoin/bips/blob/master/bip-0370.mediawiki#determining-lock-time>
     fn determine_lock_time(&self) -> Result<absolute::LockTime, DetermineLockTimeError> {
-        let require_time_based_lock_time =
-            self.inputs.iter().any(|input| input.requires_time_based_lock_time());
-        let require_height_based_lock_time =
-            self.inputs.iter().any(|input| input.requires_height_based_lock_time());
+        let mut require_time_based_lock_time = false;
+        let mut require_height_based_lock_time = false;
+        let mut all_inputs_satisfied_with_height_based_lock_time = true;
+        let mut max_height = None;
+        let mut max_time = None;
+
+        for input in &self.inputs {
+            require_time_based_lock_time |= input.requires_time_based_lock_time();
+            require_height_based_lock_time |= input.requires_height_based_lock_time();
+            all_inputs_satisfied_with_height_based_lock_time &=
+                input.is_satisfied_with_height_based_lock_time();
+            // `None` is less than `Some` so this is the maximum of the set fields.
+            max_height = max_height.max(input.min_height);
+            max_time = max_time.max(input.min_time);
+        }
 
         if require_time_based_lock_time && require_height_based_lock_time {
             return Err(DetermineLockTimeError);
         }
 
-        let have_lock_time = self.inputs.iter().any(|input| input.has_lock_time());
+        let have_lock_time = max_height.is_some() || max_time.is_some();
 
         let lock = if have_lock_time {
-            let all_inputs_satisfied_with_height_based_lock_time =
-                self.inputs.iter().all(|input| input.is_satisfied_with_height_based_lock_time());
-
             // > The lock time chosen is then the maximum value of the chosen type of lock time.
             if all_inputs_satisfied_with_height_based_lock_time {
                 // We either have only height based or we have both, in which case we must use height based.
-                let height = self
-                    .inputs
-                    .iter()
-                    .map(|input| input.min_height)
-                    .max()
-                    .expect("we know we have at least one non-none min_height field")
-                    .expect("so we know that max is non-none");
+                let height =
+                    max_height.expect("we know we have at least one non-none min_height field");
                 absolute::LockTime::from(height)
             } else {
-                let time = self
-                    .inputs
-                    .iter()
-                    .map(|input| input.min_time)
-                    .max()
-                    .expect("we know we have at least one non-none min_height field")
-                    .expect("so we know that max is non-none");
+                let time = max_time.expect("we know we have at least one non-none min_time field");
                 absolute::LockTime::from(time)
             }
         } else {
@@ -421,6 +1338,79 @@ impl Psbt {
     }
 }
 
+impl TryFrom<bitcoin::Psbt> for Psbt {
+    type Error = InvalidError;
+
+    fn try_from(psbt: bitcoin::Psbt) -> Result<Self, Self::Error> { Self::from_psbt(psbt) }
+}
+
+impl TryFrom<Psbt> for bitcoin::Psbt {
+    type Error = SanityError;
+
+    fn try_from(psbt: Psbt) -> Result<Self, Self::Error> { psbt.to_psbt() }
+}
+
+/// The number of inputs of each script type.
+///
+/// Returned by [`Psbt::count_by_type`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScriptTypeCounts {
+    /// The number of inputs spending P2TR outputs.
+    pub taproot: usize,
+    /// The number of inputs spending native segwit v0 (P2WPKH or P2WSH) outputs.
+    pub segwit_v0: usize,
+    /// The number of inputs spending P2SH outputs, including P2SH wrapped segwit.
+    pub p2sh: usize,
+    /// The number of inputs spending any other non-segwit output (e.g. P2PKH).
+    pub legacy: usize,
+}
+
+/// Classification of a PSBT by the funding scripts of its inputs.
+///
+/// Returned by [`Psbt::segwit_classification`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SegwitClass {
+    /// All inputs spend segwit outputs.
+    AllSegwit,
+    /// No inputs spend segwit outputs.
+    AllLegacy,
+    /// Some inputs spend segwit outputs and some do not.
+    ///
+    /// Mixed PSBTs need care, e.g. the legacy inputs require a `non_witness_utxo`.
+    Mixed,
+}
+
+/// Summary of the keys in a PSBT sourced from a single master key.
+///
+/// Returned by [`Psbt::keys_from_fingerprint`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyStats {
+    /// The number of inputs with at least one key sourced from the master key.
+    pub inputs: usize,
+    /// The number of outputs with at least one key sourced from the master key.
+    pub outputs: usize,
+    /// The number of distinct keys, across all inputs and outputs, sourced from the master key.
+    pub keys: usize,
+}
+
+/// Adds two amounts, all amount summation in this crate should use this function.
+///
+/// # Errors
+///
+/// If the sum overflows or exceeds [`Amount::MAX_MONEY`], since no valid transaction can spend or
+/// create that much.
+fn checked_add_amounts(a: Amount, b: Amount) -> Result<Amount, FeeError> {
+    a.checked_add(b).filter(|sum| *sum <= Amount::MAX_MONEY).ok_or(FeeError::Overflow)
+}
+
+/// Returns the error for output amounts that exceed the input amounts.
+fn negative_fee(input_amount: Amount, output_amount: Amount) -> FeeError {
+    match output_amount.checked_sub(input_amount) {
+        Some(deficit) => FeeError::NegativeFee { deficit },
+        None => FeeError::Overflow,
+    }
+}
+
 // TODO: Upstream.
 fn assert_is_valid_v2(psbt: &bitcoin::Psbt) -> Result<(), V2InvalidError> {
     use V2InvalidError::*;
@@ -446,6 +1436,18 @@ fn assert_is_valid_v2(psbt: &bitcoin::Psbt) -> Result<(), V2InvalidError> {
 pub enum DeserializeError {
     Deserialize(bitcoin::psbt::Error),
     Invalid(InvalidError),
+    /// The serialized PSBT exceeds the maximum allowed size.
+    TooLarge {
+        /// The maximum number of bytes allowed.
+        max_bytes: usize,
+    },
+    /// The PSBT has global unknown or proprietary fields (see [`Psbt::deserialize_strict`]).
+    UnexpectedUnknownFields {
+        /// The number of global unknown fields.
+        unknown: usize,
+        /// The number of global proprietary fields.
+        proprietary: usize,
+    },
 }
 
 impl fmt::Display for DeserializeError {
@@ -455,22 +1457,38 @@ impl fmt::Display for DeserializeError {
         match *self {
             Deserialize(ref e) => write_err!(f, "deserialize"; e),
             Invalid(ref e) => write_err!(f, "deserialize"; e),
+            TooLarge { max_bytes } =>
+                write!(f, "serialized PSBT exceeds the maximum size of {} bytes", max_bytes),
+            UnexpectedUnknownFields { unknown, proprietary } => write!(
+                f,
+                "PSBT has {} unknown and {} proprietary global fields",
+                unknown, proprietary
+            ),
         }
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for DeserializeError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for DeserializeError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use DeserializeError::*;
 
         match *self {
             Deserialize(ref e) => Some(e),
             Invalid(ref e) => Some(e),
+            TooLarge { .. } | UnexpectedUnknownFields { .. } => None,
         }
     }
 }
 
+impl From<bitcoin::psbt::Error> for DeserializeError {
+    fn from(e: bitcoin::psbt::Error) -> Self { Self::Deserialize(e) }
+}
+
+impl From<InvalidError> for DeserializeError {
+    fn from(e: InvalidError) -> Self { Self::Invalid(e) }
+}
+
 /// PSBT is not valid according to the Version 2 requirements.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[non_exhaustive]
@@ -495,9 +1513,9 @@ impl fmt::Display for InvalidError {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for InvalidError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for InvalidError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use InvalidError::*;
 
         match *self {
@@ -508,6 +1526,52 @@ impl std::error::Error for InvalidError {
     }
 }
 
+impl From<V0InvalidError> for InvalidError {
+    fn from(e: V0InvalidError) -> Self { Self::V0Invalid(e) }
+}
+
+impl From<V2InvalidError> for InvalidError {
+    fn from(e: V2InvalidError) -> Self { Self::V2Invalid(e) }
+}
+
+/// PSBT is not valid according to the Version 0 requirements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum V0InvalidError {
+    /// Field `unsigned_tx` is not set (PSBT_GLOBAL_UNSIGNED_TX).
+    MissingUnsignedTx,
+    /// Invalid PSBT v0 input.
+    InvalidInput(usize, input::V0InvalidError),
+    /// Invalid PSBT v0 output.
+    InvalidOutput(usize, output::V0InvalidError),
+}
+
+impl fmt::Display for V0InvalidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use V0InvalidError::*;
+
+        match *self {
+            MissingUnsignedTx =>
+                write!(f, "invalid PSBT v0, missing unsigned tx (PSBT_GLOBAL_UNSIGNED_TX)"),
+            InvalidInput(index, ref e) => write_err!(f, "invalid input for index {}", index; e),
+            InvalidOutput(index, ref e) => write_err!(f, "invalid output for index {}", index; e),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for V0InvalidError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use V0InvalidError::*;
+
+        match *self {
+            InvalidInput(_index, ref e) => Some(e),
+            InvalidOutput(_index, ref e) => Some(e),
+            MissingUnsignedTx => None,
+        }
+    }
+}
+
 /// PSBT is not valid according to the Version 2 requirements.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[non_exhaustive]
@@ -541,9 +1605,9 @@ impl fmt::Display for V2InvalidError {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for V2InvalidError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for V2InvalidError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use V2InvalidError::*;
 
         match *self {
@@ -554,8 +1618,100 @@ impl std::error::Error for V2InvalidError {
     }
 }
 
+/// Error combining two PSBTs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CombineError {
+    /// The transaction versions are not the same.
+    TxVersionMismatch {
+        /// Attempted to combine a PSBT with `this` tx version.
+        this: transaction::Version,
+        /// Into a PSBT with `that` tx version.
+        that: transaction::Version,
+    },
+    /// The PSBTs do not have the same number of inputs.
+    InputCountMismatch {
+        /// Attempted to combine a PSBT with `this` many inputs.
+        this: usize,
+        /// Into a PSBT with `that` many inputs.
+        that: usize,
+    },
+    /// The PSBTs do not have the same number of outputs.
+    OutputCountMismatch {
+        /// Attempted to combine a PSBT with `this` many outputs.
+        this: usize,
+        /// Into a PSBT with `that` many outputs.
+        that: usize,
+    },
+    /// Xpubs have inconsistent key sources.
+    InconsistentKeySources(InconsistentKeySourcesError),
+    /// Error combining the inputs at `index`.
+    Input {
+        /// The index of the input.
+        index: usize,
+        /// The error combining the input.
+        error: input::CombineError,
+    },
+    /// Error combining the outputs at `index`.
+    Output {
+        /// The index of the output.
+        index: usize,
+        /// The error combining the output.
+        error: output::CombineError,
+    },
+    /// SIGHASH_SINGLE is set and the input/output pairs of the PSBTs differ.
+    SighashSinglePairingConflict {
+        /// The first index at which the input/output pairs differ.
+        index: usize,
+    },
+}
+
+impl fmt::Display for CombineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use CombineError::*;
+
+        match *self {
+            TxVersionMismatch { ref this, ref that } =>
+                write!(f, "combine two PSBTs with different tx versions: {:?} {:?}", this, that),
+            InputCountMismatch { this, that } =>
+                write!(f, "combine two PSBTs with different input counts: {} {}", this, that),
+            OutputCountMismatch { this, that } =>
+                write!(f, "combine two PSBTs with different output counts: {} {}", this, that),
+            InconsistentKeySources(ref e) => write_err!(f, "combine global"; e),
+            Input { index, ref error } => write_err!(f, "combine input {}", index; error),
+            Output { index, ref error } => write_err!(f, "combine output {}", index; error),
+            SighashSinglePairingConflict { index } => write!(
+                f,
+                "combine two PSBTs with SIGHASH_SINGLE set and different pairing at index {}",
+                index
+            ),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for CombineError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use CombineError::*;
+
+        match *self {
+            InconsistentKeySources(ref e) => Some(e),
+            Input { ref error, .. } => Some(error),
+            Output { ref error, .. } => Some(error),
+            TxVersionMismatch { .. }
+            | InputCountMismatch { .. }
+            | OutputCountMismatch { .. }
+            | SighashSinglePairingConflict { .. } => None,
+        }
+    }
+}
+
+impl From<InconsistentKeySourcesError> for CombineError {
+    fn from(e: InconsistentKeySourcesError) -> Self { Self::InconsistentKeySources(e) }
+}
+
 #[rustfmt::skip]
-mod prelude {
+mod internal_prelude {
     #![allow(unused_imports)]
 
     #[cfg(all(not(feature = "std"), not(test)))]
@@ -573,3 +1729,346 @@ mod prelude {
     #[cfg(any(feature = "std", test))]
     pub use std::collections::{BTreeMap, BTreeSet, btree_map, BinaryHeap};
 }
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::bip32::DerivationPath;
+    use bitcoin::key::{Keypair, TapTweak as _};
+    use bitcoin::secp256k1::{self, Secp256k1, SecretKey};
+    use bitcoin::sighash::SighashCache;
+    use bitcoin::{script, taproot, CompressedPublicKey, Network, OutPoint, PrivateKey, Witness};
+
+    use super::*;
+    use crate::output::test_output;
+
+    fn secret_key(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).expect("valid secret key")
+    }
+
+    fn pubkey(byte: u8) -> secp256k1::PublicKey {
+        secp256k1::PublicKey::from_secret_key(&Secp256k1::signing_only(), &secret_key(byte))
+    }
+
+    /// Returns an input spending P2WPKH output `vout` of the coinbase prevout txid.
+    fn input(vout: u32) -> Input {
+        let script_pubkey = ScriptBuf::new_p2wpkh(&CompressedPublicKey(pubkey(1)).wpubkey_hash());
+        let mut input = Input::new(&OutPoint { vout, ..OutPoint::COINBASE_PREVOUT });
+        input.witness_utxo = Some(TxOut { value: Amount::from_sat(10_000), script_pubkey });
+        input
+    }
+
+    /// Returns a PSBT with `inputs` inputs and `outputs` outputs.
+    fn psbt(inputs: u32, outputs: u64) -> Psbt {
+        let mut constructor = Constructor::<Modifiable>::new();
+        for vout in 0..inputs {
+            constructor = constructor.input(input(vout));
+        }
+        for sats in 0..outputs {
+            constructor = constructor.output(test_output(1_000 + sats));
+        }
+        constructor.into_inner().expect("valid lock time combination")
+    }
+
+    #[test]
+    fn combine_input_count_mismatch() {
+        let err = psbt(2, 1).combine_with(psbt(1, 1)).expect_err("different input counts");
+        assert_eq!(err, CombineError::InputCountMismatch { this: 2, that: 1 });
+
+        let err = psbt(1, 1).combine_with(psbt(3, 1)).expect_err("different input counts");
+        assert_eq!(err, CombineError::InputCountMismatch { this: 1, that: 3 });
+    }
+
+    #[test]
+    fn set_input_sequence_clears_signatures_on_change() {
+        let mut psbt = psbt(1, 1);
+        psbt.inputs[0].final_script_sig = Some(ScriptBuf::new());
+
+        // An unset sequence number is `Sequence::MAX`, setting it again keeps the signatures.
+        psbt.set_input_sequence(0, Sequence::MAX).expect("valid index");
+        assert!(psbt.inputs[0].final_script_sig.is_some());
+
+        psbt.set_input_sequence(0, Sequence::ENABLE_RBF_NO_LOCKTIME).expect("valid index");
+        assert!(psbt.inputs[0].final_script_sig.is_none());
+        assert_eq!(psbt.inputs[0].sequence, Some(Sequence::ENABLE_RBF_NO_LOCKTIME));
+    }
+
+    #[test]
+    fn to_psbt_v2_rejects_stale_counts() {
+        let mut psbt = psbt(2, 1);
+        psbt.inputs.pop();
+
+        let err = psbt.clone().to_psbt_v2().expect_err("input count is stale");
+        assert_eq!(err, SanityError::InputCountMismatch { count: 2, len: 1 });
+
+        // Serialization always writes the actual counts.
+        let v2 = bitcoin::Psbt::deserialize(&psbt.serialize()).expect("valid v2 PSBT");
+        assert_eq!(v2.input_count, Some(1));
+
+        psbt.recompute_counts();
+        assert!(psbt.to_psbt_v2().is_ok());
+    }
+
+    #[test]
+    fn combine_output_count_mismatch() {
+        let err = psbt(1, 2).combine_with(psbt(1, 1)).expect_err("different output counts");
+        assert_eq!(err, CombineError::OutputCountMismatch { this: 2, that: 1 });
+    }
+
+    #[test]
+    fn combine_count_mismatch_with_sighash_single() {
+        let sighash_single = |mut psbt: Psbt| {
+            psbt.tx_modifiable_flags |= SIGHASH_SINGLE;
+            psbt
+        };
+
+        let err = sighash_single(psbt(2, 1))
+            .combine_with(psbt(1, 1))
+            .expect_err("different input counts");
+        assert_eq!(err, CombineError::InputCountMismatch { this: 2, that: 1 });
+
+        let err = psbt(1, 1)
+            .combine_with(sighash_single(psbt(1, 2)))
+            .expect_err("different output counts");
+        assert_eq!(err, CombineError::OutputCountMismatch { this: 1, that: 2 });
+    }
+
+    #[test]
+    fn combine_tx_modifiable_flags_is_commutative() {
+        for this in 0..8 {
+            for that in 0..8 {
+                let mut a = psbt(1, 1);
+                a.tx_modifiable_flags = this;
+                let mut b = psbt(1, 1);
+                b.tx_modifiable_flags = that;
+
+                let ab = a.clone().combine_with(b.clone()).expect("combine a with b");
+                let ba = b.combine_with(a).expect("combine b with a");
+
+                let want = (this & that & (INPUTS_MODIFIABLE | OUTPUTS_MODIFIABLE))
+                    | ((this | that) & SIGHASH_SINGLE);
+                assert_eq!(ab.tx_modifiable_flags, want, "flags {:#05b} {:#05b}", this, that);
+                assert_eq!(ba.tx_modifiable_flags, want, "flags {:#05b} {:#05b}", that, this);
+            }
+        }
+    }
+
+    #[test]
+    fn clear_tx_modifiable_per_sighash_type() {
+        use bitcoin::sighash::{EcdsaSighashType as Ecdsa, TapSighashType};
+
+        // Sighash byte, inputs modifiable, outputs modifiable, has SIGHASH_SINGLE.
+        let table = [
+            (TapSighashType::Default as u8, false, false, false),
+            (Ecdsa::All as u8, false, false, false),
+            (Ecdsa::None as u8, false, true, false),
+            (Ecdsa::Single as u8, false, true, true),
+            (Ecdsa::AllPlusAnyoneCanPay as u8, true, false, false),
+            (Ecdsa::NonePlusAnyoneCanPay as u8, true, true, false),
+            (Ecdsa::SinglePlusAnyoneCanPay as u8, true, true, true),
+        ];
+
+        for (sighash, inputs, outputs, single) in table {
+            let mut psbt = psbt(1, 1);
+            assert!(psbt.is_inputs_modifiable() && psbt.is_outputs_modifiable());
+            assert!(!psbt.has_sighash_single());
+
+            psbt.clear_tx_modifiable(sighash);
+
+            assert_eq!(psbt.is_inputs_modifiable(), inputs, "sighash {:#04x}", sighash);
+            assert_eq!(psbt.is_outputs_modifiable(), outputs, "sighash {:#04x}", sighash);
+            assert_eq!(psbt.has_sighash_single(), single, "sighash {:#04x}", sighash);
+        }
+    }
+
+    #[test]
+    fn estimated_vsize_finalized_p2sh_p2wpkh() {
+        let redeem_script = ScriptBuf::new_p2wpkh(&CompressedPublicKey(pubkey(1)).wpubkey_hash());
+        let script_pubkey = ScriptBuf::new_p2sh(&redeem_script.script_hash());
+        let push = <&script::PushBytes>::try_from(redeem_script.as_bytes()).expect("22 bytes");
+        let script_sig = script::Builder::new().push_slice(push).into_script();
+        let witness = Witness::from_slice(&[vec![0x30; 72], pubkey(1).serialize().to_vec()]);
+
+        // The Finalizer has cleared the `redeem_script`.
+        let mut input = input(0);
+        input.witness_utxo = Some(TxOut { value: Amount::from_sat(10_000), script_pubkey });
+        input.final_script_sig = Some(script_sig.clone());
+        input.final_script_witness = Some(witness.clone());
+
+        let psbt = Constructor::<Modifiable>::new()
+            .input(input)
+            .output(test_output(9_000))
+            .into_inner()
+            .expect("valid lock time combination");
+
+        let mut tx = psbt.unsigned_tx().expect("valid lock time combination");
+        tx.input[0].script_sig = script_sig;
+        tx.input[0].witness = witness;
+        assert_eq!(psbt.estimated_vsize().expect("estimate vsize"), tx.vsize());
+    }
+
+    #[test]
+    fn estimated_vsize_matches_extracted_transaction() {
+        let secp = Secp256k1::new();
+        let sk = PrivateKey::new(secret_key(1), Network::Bitcoin);
+        let pk = sk.public_key(&secp);
+        let keypair = Keypair::from_secret_key(&secp, &secret_key(2));
+        let (xonly, _) = keypair.x_only_public_key();
+        let key_source = (Fingerprint::from([0xd3, 0x4d, 0xb3, 0x3f]), DerivationPath::master());
+
+        // `input` spends a P2WPKH output locked to `pubkey(1)`.
+        let mut p2wpkh = input(0);
+        p2wpkh.bip32_derivation.insert(pk.inner, key_source);
+        let mut p2tr = input(1);
+        p2tr.witness_utxo = Some(TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new_p2tr(&secp, xonly, None),
+        });
+
+        let psbt = Constructor::<Modifiable>::new()
+            .input(p2wpkh)
+            .input(p2tr)
+            .output(test_output(19_000))
+            .into_inner()
+            .expect("valid lock time combination");
+        let estimate = psbt.estimated_vsize().expect("estimate unsigned vsize");
+
+        let k = [(pk, sk)].into_iter().collect::<BTreeMap<_, _>>();
+        let signer = Signer::new(psbt).expect("valid lock time combination");
+        let (mut psbt, _) = signer.sign(&k, &secp).expect("sign P2WPKH input");
+
+        let tx = psbt.unsigned_tx().expect("valid lock time combination");
+        let mut cache = SighashCache::new(&tx);
+        let (msg, sighash_type) = psbt.taproot_sighash(1, None, &mut cache).expect("sighash");
+        let tweaked = keypair.tap_tweak(&secp, None).to_inner();
+        let signature = secp.sign_schnorr_no_aux_rand(&msg, &tweaked);
+        let tap_sig = taproot::Signature { signature, sighash_type };
+
+        let sig = psbt.inputs[0].partial_sigs[&pk];
+        psbt.inputs[0].final_script_sig = Some(ScriptBuf::new());
+        psbt.inputs[0].final_script_witness = Some(Witness::p2wpkh(&sig, &pk.inner));
+        psbt.inputs[1].final_script_sig = Some(ScriptBuf::new());
+        psbt.inputs[1].final_script_witness = Some(Witness::p2tr_key_spend(&tap_sig));
+        let finalized = psbt.estimated_vsize().expect("estimate finalized vsize");
+
+        let extractor = Extractor::new(psbt).expect("all inputs finalized");
+        let vsize = extractor.extract_tx().expect("extract transaction").vsize();
+
+        assert_eq!(finalized, vsize);
+        // The unsigned estimate is an upper bound, ECDSA signatures vary in length.
+        assert!(estimate >= vsize, "estimate {} vsize {}", estimate, vsize);
+        assert!(estimate - vsize <= 1, "estimate {} vsize {}", estimate, vsize);
+    }
+
+    #[test]
+    fn amounts_beyond_max_money_overflow() {
+        let max = Amount::MAX_MONEY.to_sat();
+
+        let mut outputs = psbt(1, 0);
+        outputs.modify_outputs(|outputs| outputs.extend([test_output(max), test_output(max)]));
+        let spk = outputs.outputs[0].script_pubkey.clone();
+        assert_eq!(outputs.total_paid_to(&spk), Err(FeeError::Overflow));
+        assert_eq!(outputs.fee(), Err(FeeError::Overflow));
+
+        let mut inputs = psbt(2, 1);
+        for input in &mut inputs.inputs {
+            input.witness_utxo.as_mut().expect("witness UTXO").value = Amount::MAX_MONEY;
+        }
+        assert_eq!(inputs.fee(), Err(FeeError::Overflow));
+    }
+
+    #[test]
+    fn negative_fee_deficit() {
+        let mut psbt = psbt(1, 0);
+        psbt.modify_outputs(|outputs| outputs.extend([test_output(6_000), test_output(7_000)]));
+        let deficit = Amount::from_sat(3_000);
+        assert_eq!(psbt.fee(), Err(FeeError::NegativeFee { deficit }));
+
+        let err =
+            psbt.check_output_affordable(&test_output(1_000)).expect_err("outputs exceed inputs");
+        assert_eq!(err, FeeError::NegativeFee { deficit: Amount::from_sat(4_000) });
+    }
+
+    #[test]
+    fn requires_non_witness_utxo_checks_index() {
+        let mut psbt = psbt(2, 1);
+        psbt.inputs[1].witness_utxo = None;
+
+        assert_eq!(psbt.requires_non_witness_utxo(0), Ok(false));
+        assert_eq!(
+            psbt.requires_non_witness_utxo(1),
+            Err(NonWitnessUtxoError::FundingUtxo(FundingUtxoError::MissingUtxo))
+        );
+        assert_eq!(
+            psbt.requires_non_witness_utxo(2),
+            Err(NonWitnessUtxoError::IndexOutOfBounds(IndexOutOfBoundsError::Inputs {
+                index: 2,
+                length: 2
+            }))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_json_roundtrip_proprietary() {
+        let key = |subtype| raw::ProprietaryKey {
+            prefix: b"psbt-v2".to_vec(),
+            subtype,
+            key: vec![0x01, 0x02],
+        };
+        let mut psbt = psbt(1, 1);
+        psbt.inputs[0].proprietary.insert(key(0x00), vec![0xaa; 4]);
+        psbt.outputs[0].proprietary.insert(key(0x01), vec![0xbb; 4]);
+
+        let json = serde_json::to_string(&psbt).expect("serialize to JSON");
+        let got: Psbt = serde_json::from_str(&json).expect("deserialize from JSON");
+
+        assert_eq!(got, psbt);
+        assert_eq!(got.serialize(), psbt.serialize());
+    }
+}
+
+#[cfg(bench)]
+mod benches {
+    use bitcoin::secp256k1::{self, Secp256k1, SecretKey};
+    use bitcoin::sighash::EcdsaSighashType;
+    use bitcoin::{ecdsa, CompressedPublicKey, OutPoint};
+    use test::{black_box, Bencher};
+
+    use super::*;
+
+    const INPUTS: u32 = 50;
+
+    /// Returns a PSBT spending `INPUTS` P2WPKH outputs, `funded` controls if the inputs have UTXOs.
+    fn psbt(pk: secp256k1::PublicKey, funded: bool) -> Psbt {
+        let script_pubkey = ScriptBuf::new_p2wpkh(&CompressedPublicKey(pk).wpubkey_hash());
+        let mut constructor = Constructor::<Modifiable>::new();
+        for vout in 0..INPUTS {
+            let mut input = Input::new(&OutPoint { vout, ..OutPoint::COINBASE_PREVOUT });
+            if funded {
+                let value = Amount::from_sat(10_000);
+                input.witness_utxo = Some(TxOut { value, script_pubkey: script_pubkey.clone() });
+            }
+            constructor = constructor.input(input);
+        }
+        constructor.into_inner().expect("valid lock time combination")
+    }
+
+    #[bench]
+    pub fn bench_combine_with_one_signed_input(bh: &mut Bencher) {
+        let secp = Secp256k1::signing_only();
+        let sk = SecretKey::from_slice(&[1; 32]).expect("valid secret key");
+        let pk = secp256k1::PublicKey::from_secret_key(&secp, &sk);
+
+        let this = psbt(pk, true);
+        // Only input 0 has new data, the other inputs are empty.
+        let mut that = psbt(pk, false);
+        let signature = secp.sign_ecdsa(&secp256k1::Message::from_digest([1; 32]), &sk);
+        let sig = ecdsa::Signature { signature, sighash_type: EcdsaSighashType::All };
+        that.inputs[0].partial_sigs.insert(bitcoin::PublicKey::new(pk), sig);
+
+        bh.iter(|| {
+            let combined = this.clone().combine_with(that.clone()).expect("combine PSBTs");
+            black_box(combined);
+        });
+    }
+}
@@ -2,13 +2,17 @@
 
 use core::fmt;
 
-use bitcoin::bip32::KeySource;
+use bitcoin::address::FromScriptError;
+use bitcoin::bip32::{Fingerprint, KeySource};
 use bitcoin::key::XOnlyPublicKey;
 use bitcoin::psbt::raw;
 use bitcoin::taproot::{TapLeafHash, TapTree};
-use bitcoin::{secp256k1, Amount, ScriptBuf};
+use bitcoin::{secp256k1, Address, Amount, Network, ScriptBuf, TxOut};
 
-use crate::prelude::BTreeMap;
+use crate::error::ScriptField;
+#[cfg(any(feature = "std", rust_v_1_81))]
+use crate::error::StdError;
+use crate::internal_prelude::BTreeMap;
 
 /// A PSBT output guaranteed to be valid for PSBT version 2.
 ///
@@ -42,6 +46,14 @@ pub struct Output {
     /// Map of Taproot x only keys to origin info and leaf hashes contained in it.
     #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq"))]
     pub tap_key_origins: BTreeMap<XOnlyPublicKey, (Vec<TapLeafHash>, KeySource)>,
+
+    /// Proprietary key-value pairs for this output.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq_byte_values"))]
+    pub proprietary: BTreeMap<raw::ProprietaryKey, Vec<u8>>,
+
+    /// Unknown key-value pairs for this output.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::btreemap_as_seq_byte_values"))]
+    pub unknown: BTreeMap<raw::Key, Vec<u8>>,
 }
 
 impl Output {
@@ -60,14 +72,19 @@ impl Output {
             tap_internal_key: output.tap_internal_key,
             tap_tree: output.tap_tree,
             tap_key_origins: output.tap_key_origins,
+            proprietary: output.proprietary,
+            unknown: output.unknown,
         })
     }
 
-    pub(crate) fn from_v0(output: bitcoin::psbt::Output, txout: TxOut) -> Result<Output, V0InvalidError> {
+    pub(crate) fn from_v0(
+        output: bitcoin::psbt::Output,
+        txout: TxOut,
+    ) -> Result<Output, V0InvalidError> {
         assert_is_valid_v0(&output)?;
 
-        let amount = txout.amount.unwrap();
-        let script_pubkey = txout.script_pubkey.unwrap();
+        let amount = txout.value;
+        let script_pubkey = txout.script_pubkey;
 
         Ok(Output {
             redeem_script: output.redeem_script,
@@ -78,6 +95,8 @@ impl Output {
             tap_internal_key: output.tap_internal_key,
             tap_tree: output.tap_tree,
             tap_key_origins: output.tap_key_origins,
+            proprietary: output.proprietary,
+            unknown: output.unknown,
         })
     }
         
@@ -92,8 +111,8 @@ impl Output {
             tap_internal_key: self.tap_internal_key,
             tap_tree: self.tap_tree,
             tap_key_origins: self.tap_key_origins,
-            proprietary: BTeeMap::default(),
-            unknown: BTeeMap::default(),
+            proprietary: self.proprietary,
+            unknown: self.unknown,
         }
     }
 
@@ -110,6 +129,46 @@ impl Output {
         TxOut { value: self.amount, script_pubkey: self.script_pubkey.clone() }
     }
 
+    /// Returns the address this output pays to on `network`.
+    ///
+    /// # Errors
+    ///
+    /// If the `script_pubkey` is non-standard and has no address representation.
+    pub fn address(&self, network: Network) -> Result<Address, FromScriptError> {
+        Address::from_script(&self.script_pubkey, network)
+    }
+
+    /// Returns true if this output probably pays to a key controlled by master key `my_fingerprint`.
+    ///
+    /// Checks for a key sourced from `my_fingerprint` in `bip32_derivation` or `tap_key_origins`.
+    ///
+    /// This is a heuristic, the key origin data is set by the PSBT constructor/updater and a
+    /// malicious one can set it to anything. Use this for display purposes only, it is not a
+    /// security boundary.
+    pub fn is_probably_change(&self, my_fingerprint: Fingerprint) -> bool {
+        self.bip32_derivation.values().any(|(fp, _)| *fp == my_fingerprint)
+            || self.tap_key_origins.values().any(|(_, (fp, _))| *fp == my_fingerprint)
+    }
+
+    /// Sets the taproot internal key and script tree for this output.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if `script_pubkey` is not a P2TR output that commits to `internal_key` and
+    /// `tree`.
+    pub fn set_taproot_tree(&mut self, internal_key: XOnlyPublicKey, tree: TapTree) {
+        debug_assert!(self.script_pubkey.is_p2tr(), "taproot tree set on non-taproot output");
+        #[cfg(debug_assertions)]
+        {
+            let secp = secp256k1::Secp256k1::verification_only();
+            let want = ScriptBuf::new_p2tr(&secp, internal_key, Some(tree.root_hash()));
+            debug_assert_eq!(self.script_pubkey, want, "taproot tree does not match script_pubkey");
+        }
+
+        self.tap_internal_key = Some(internal_key);
+        self.tap_tree = Some(tree);
+    }
+
     /// Combines this [`Output`] with `other` `Output` (as described by BIP-174).
     pub fn combine(&mut self, other: Self) -> Result<(), CombineError> {
         if self.amount != other.amount {
@@ -123,19 +182,101 @@ impl Output {
             });
         }
 
+        // Differing scripts mean the PSBTs disagree about how the output will be spent.
+        if let (Some(this), Some(that)) = (&self.redeem_script, &other.redeem_script) {
+            if this != that {
+                return Err(CombineError::ScriptConflict { which: ScriptField::Redeem });
+            }
+        }
+        if let (Some(this), Some(that)) = (&self.witness_script, &other.witness_script) {
+            if this != that {
+                return Err(CombineError::ScriptConflict { which: ScriptField::Witness });
+            }
+        }
+
         v2_combine_option!(redeem_script, self, other);
         v2_combine_option!(witness_script, self, other);
-        v2_combine_map!(bip32_derivations, self, other);
+        v2_combine_map!(bip32_derivation, self, other);
         v2_combine_option!(tap_internal_key, self, other);
         v2_combine_option!(tap_tree, self, other);
         v2_combine_map!(tap_key_origins, self, other);
-        v2_combine_map!(proprietaries, self, other);
-        v2_combine_map!(unknowns, self, other);
+        v2_combine_map!(proprietary, self, other);
+        v2_combine_map!(unknown, self, other);
 
         Ok(())
     }
 }
 
+impl From<Output> for bitcoin::psbt::Output {
+    /// Converts to a `rust-bitcoin` output with the PSBT version 2 fields set.
+    fn from(output: Output) -> Self { output.to_v2() }
+}
+
+impl TryFrom<bitcoin::psbt::Output> for Output {
+    type Error = V2InvalidError;
+
+    /// Converts from a `rust-bitcoin` output, the PSBT version 2 required fields must be set.
+    fn try_from(output: bitcoin::psbt::Output) -> Result<Self, Self::Error> {
+        Self::from_v2(output)
+    }
+}
+
+/// Error combining two output maps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CombineError {
+    /// The amounts are not the same.
+    AmountMismatch {
+        /// Attempted to combine a PSBT with `this` amount.
+        this: Amount,
+        /// Into a PSBT with `that` amount.
+        that: Amount,
+    },
+    /// The script_pubkeys are not the same.
+    ScriptPubkeyMismatch {
+        /// Attempted to combine a PSBT with `this` script_pubkey.
+        this: ScriptBuf,
+        /// Into a PSBT with `that` script_pubkey.
+        that: ScriptBuf,
+    },
+    /// The outputs have different redeem or witness scripts.
+    ///
+    /// The output index is available from the wrapping [`crate::CombineError::Output`].
+    ScriptConflict {
+        /// The conflicting field.
+        which: ScriptField,
+    },
+}
+
+impl fmt::Display for CombineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use CombineError::*;
+
+        match *self {
+            AmountMismatch { ref this, ref that } =>
+                write!(f, "combine two PSBT outputs with different amounts: {} {}", this, that),
+            ScriptPubkeyMismatch { ref this, ref that } => write!(
+                f,
+                "combine two PSBT outputs with different script_pubkeys: {:x} {:x}",
+                this, that
+            ),
+            ScriptConflict { which } =>
+                write!(f, "combine two PSBT outputs with conflicting {} values", which),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for CombineError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use CombineError::*;
+
+        match *self {
+            AmountMismatch { .. } | ScriptPubkeyMismatch { .. } | ScriptConflict { .. } => None,
+        }
+    }
+}
+
 // TODO: Upstream.
 pub(crate) fn assert_is_valid_v2(output: &bitcoin::psbt::Output) -> Result<(), V2InvalidError> {
     use V2InvalidError::*;
@@ -173,9 +314,9 @@ impl fmt::Display for V2InvalidError {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for V2InvalidError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for V2InvalidError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use V2InvalidError::*;
 
         match *self {
@@ -185,13 +326,13 @@ impl std::error::Error for V2InvalidError {
 }
 
 // TODO: Upstream.
-pub(crate) fn assert_is_valid_v0(input: &bitcoin::psbt::Input) -> Result<(), V0InvalidError> {
+pub(crate) fn assert_is_valid_v0(output: &bitcoin::psbt::Output) -> Result<(), V0InvalidError> {
     use V0InvalidError::*;
 
-    if input.sequence.is_some() {
+    if output.amount.is_some() {
         return Err(HasAmount);
     }
-    if input.script_pubkey.is_some() {
+    if output.script_pubkey.is_some() {
         return Err(HasScriptPubkey);
     }
 
@@ -210,20 +351,20 @@ pub enum V0InvalidError {
 
 impl fmt::Display for V0InvalidError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use IsValidPsbtV2Error::*;
+        use V0InvalidError::*;
 
         match *self {
             HasAmount =>
-                write!(f, "invalid v2 input, `amount` should be excluded (PSBT_OUT_AMOUNT)"),
+                write!(f, "invalid v0 output, `amount` should be excluded (PSBT_OUT_AMOUNT)"),
             HasScriptPubkey =>
-                write!(f, "invalid v2 input, `min_time` should be excluded (PSBT_OUT_SCRIPT)"),
+                write!(f, "invalid v0 output, `script_pubkey` should be excluded (PSBT_OUT_SCRIPT)"),
         }
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for V0InvalidError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for V0InvalidError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use V0InvalidError::*;
 
         match *self {
@@ -231,3 +372,89 @@ impl std::error::Error for V0InvalidError {
         }
     }
 }
+
+/// Returns an output paying `sats` to an `OP_RETURN` script with no other fields set.
+#[cfg(test)]
+pub(crate) fn test_output(sats: u64) -> Output {
+    let output = bitcoin::psbt::Output {
+        amount: Some(Amount::from_sat(sats)),
+        script_pubkey: Some(ScriptBuf::from_bytes(vec![0x6a])),
+        ..Default::default()
+    };
+    Output::from_v2(output).expect("valid v2 output")
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::bip32::DerivationPath;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::taproot::TaprootBuilder;
+
+    use super::*;
+
+    fn pubkey(byte: u8) -> secp256k1::PublicKey {
+        let sk = SecretKey::from_slice(&[byte; 32]).expect("valid secret key");
+        secp256k1::PublicKey::from_secret_key(&Secp256k1::signing_only(), &sk)
+    }
+
+    fn key_source(child: u32) -> KeySource {
+        let path = format!("m/86'/0'/0'/1/{}", child);
+        let path = DerivationPath::from_str(&path).expect("valid derivation path");
+        (Fingerprint::from([0xd3, 0x4d, 0xb3, 0x3f]), path)
+    }
+
+    #[test]
+    fn combine_keeps_fields_set_on_one_side() {
+        let redeem_script = ScriptBuf::from_bytes(vec![0x00, 0x14, 0x01]);
+        let witness_script = ScriptBuf::from_bytes(vec![0x51]);
+        let (internal_key, _) = pubkey(1).x_only_public_key();
+        let (xonly, _) = pubkey(2).x_only_public_key();
+        let leaf = ScriptBuf::from_bytes(vec![0x52]);
+        let tree = TaprootBuilder::new().add_leaf(0, leaf).expect("valid depth");
+        let tree = TapTree::try_from(tree).expect("complete tree");
+
+        let mut a = test_output(50_000);
+        a.redeem_script = Some(redeem_script.clone());
+        a.witness_script = Some(witness_script.clone());
+        a.tap_internal_key = Some(internal_key);
+        a.bip32_derivation.insert(pubkey(3), key_source(0));
+
+        let mut b = test_output(50_000);
+        b.tap_tree = Some(tree.clone());
+        b.tap_key_origins.insert(xonly, (vec![], key_source(1)));
+        b.bip32_derivation.insert(pubkey(3), key_source(0));
+        b.bip32_derivation.insert(pubkey(4), key_source(2));
+
+        for (mut this, that) in [(a.clone(), b.clone()), (b, a)] {
+            this.combine(that).expect("combine outputs");
+
+            assert_eq!(this.redeem_script.as_ref(), Some(&redeem_script));
+            assert_eq!(this.witness_script.as_ref(), Some(&witness_script));
+            assert_eq!(this.tap_internal_key, Some(internal_key));
+            assert_eq!(this.tap_tree.as_ref(), Some(&tree));
+            assert_eq!(this.tap_key_origins.get(&xonly), Some(&(vec![], key_source(1))));
+            assert_eq!(this.bip32_derivation.len(), 2);
+            assert_eq!(this.bip32_derivation.get(&pubkey(3)), Some(&key_source(0)));
+            assert_eq!(this.bip32_derivation.get(&pubkey(4)), Some(&key_source(2)));
+        }
+    }
+
+    #[test]
+    fn combine_conflicting_scripts() {
+        let mut a = test_output(50_000);
+        a.redeem_script = Some(ScriptBuf::from_bytes(vec![0x51]));
+        let mut b = test_output(50_000);
+        b.redeem_script = Some(ScriptBuf::from_bytes(vec![0x52]));
+        let err = a.combine(b).expect_err("conflicting redeem_script");
+        assert_eq!(err, CombineError::ScriptConflict { which: ScriptField::Redeem });
+
+        let mut a = test_output(50_000);
+        a.witness_script = Some(ScriptBuf::from_bytes(vec![0x51]));
+        let mut b = test_output(50_000);
+        b.witness_script = Some(ScriptBuf::from_bytes(vec![0x52]));
+        let err = a.combine(b).expect_err("conflicting witness_script");
+        assert_eq!(err, CombineError::ScriptConflict { which: ScriptField::Witness });
+    }
+}
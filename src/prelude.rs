@@ -0,0 +1,20 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Re-exports of the types needed for a typical PSBT workflow.
+//!
+//! A single glob import covers constructing, signing, and extracting a transaction.
+//!
+//! ```
+//! use psbt_v2::prelude::*;
+//! ```
+
+pub use bitcoin::absolute::LockTime;
+pub use bitcoin::transaction::Version;
+pub use bitcoin::{Amount, ScriptBuf, Sequence, TxOut, Txid};
+
+#[cfg(feature = "miniscript")]
+pub use crate::Finalizer;
+pub use crate::{
+    Constructor, Creator, Extractor, Input, InputBuilder, InputsOnlyModifiable, Modifiable,
+    Output, OutputsOnlyModifiable, Psbt, Session, Signer, Updater,
+};
@@ -5,8 +5,8 @@
 use core::marker::PhantomData;
 
 use crate::error::{
-    DetermineLockTimeError, InputsNotModifiableError, OutputsNotModifiableError,
-    PsbtNotModifiableError,
+    AddInputError, BuildError, DetermineLockTimeError, FeeError, InputsNotModifiableError,
+    OutputsNotModifiableError, PsbtNotModifiableError,
 };
 use crate::roles::creator::Creator;
 use crate::roles::updater::Updater;
@@ -22,7 +22,8 @@ pub struct Constructor<T>(Psbt, PhantomData<T>);
 impl<T: Mod> Constructor<T> {
     /// Returns a PSBT [`Updater`] once construction is completed.
     pub fn updater(self) -> Result<Updater, DetermineLockTimeError> {
-        Updater::from_psbt(self.no_more_inputs().no_more_outputs().psbt()?)
+        // `into_inner` has already checked the lock time can be determined.
+        Ok(Updater(self.no_more_inputs().no_more_outputs().into_inner()?))
     }
 
     /// Marks that the `Psbt` can not have any more inputs added to it.
@@ -46,6 +47,21 @@ impl<T: Mod> Constructor<T> {
         let _ = self.0.determine_lock_time()?;
         Ok(self.0)
     }
+
+    /// Validates and returns the finished [`Psbt`].
+    ///
+    /// Unlike [`Self::into_inner`], which only checks the lock time can be determined, this
+    /// function checks:
+    ///
+    /// - The input and output counts are consistent with the inputs and outputs.
+    /// - The lock time can be determined.
+    /// - No two inputs spend the same outpoint.
+    /// - If the SIGHASH_SINGLE flag is set, every input with a `SIGHASH_SINGLE` signature has a
+    ///   corresponding output.
+    pub fn build(self) -> Result<Psbt, BuildError> {
+        self.0.validate()?;
+        Ok(self.0)
+    }
 }
 
 impl Constructor<Modifiable> {
@@ -70,18 +86,46 @@ impl Constructor<Modifiable> {
     pub(crate) fn from_psbt_unchecked(psbt: Psbt) -> Self { Self(psbt, PhantomData) }
 
     /// Adds an input to the PSBT.
+    ///
+    /// This function does not check for duplicate inputs, see [`Psbt::has_duplicate_inputs`]. For
+    /// untrusted input data use [`Self::try_input`].
     pub fn input(mut self, input: Input) -> Self {
         self.0.inputs.push(input);
         self.0.input_count += 1;
         self
     }
 
+    /// Adds an input to the PSBT after checking it is valid.
+    ///
+    /// # Errors
+    ///
+    /// If the PSBT already has an input spending the same outpoint or if the input's
+    /// `spent_output_index` is out of bounds for its `non_witness_utxo`.
+    pub fn try_input(self, input: Input) -> Result<Self, AddInputError> {
+        self.0.check_new_input(&input)?;
+        Ok(self.input(input))
+    }
+
     /// Adds an output to the PSBT.
     pub fn output(mut self, output: Output) -> Self {
         self.0.outputs.push(output);
         self.0.output_count += 1;
         self
     }
+
+    /// Adds an output to the PSBT after checking the inputs can fund it.
+    ///
+    /// This is an approximate check to catch gross overspends during construction, the fee is
+    /// ignored i.e., the output amounts may equal the input amounts.
+    ///
+    /// # Errors
+    ///
+    /// If any input does not have a funding UTXO or if, after adding `output`, the sum of the
+    /// output amounts would exceed the sum of the input amounts.
+    pub fn output_with_fee_check(self, output: Output) -> Result<Self, FeeError> {
+        self.0.check_output_affordable(&output)?;
+        Ok(self.output(output))
+    }
 }
 
 // Useful if the Creator and Constructor are a single entity.
@@ -109,11 +153,25 @@ impl Constructor<InputsOnlyModifiable> {
     pub(crate) fn from_psbt_unchecked(psbt: Psbt) -> Self { Self(psbt, PhantomData) }
 
     /// Adds an input to the PSBT.
+    ///
+    /// This function does not check for duplicate inputs, see [`Psbt::has_duplicate_inputs`]. For
+    /// untrusted input data use [`Self::try_input`].
     pub fn input(mut self, input: Input) -> Self {
         self.0.inputs.push(input);
         self.0.input_count += 1;
         self
     }
+
+    /// Adds an input to the PSBT after checking it is valid.
+    ///
+    /// # Errors
+    ///
+    /// If the PSBT already has an input spending the same outpoint or if the input's
+    /// `spent_output_index` is out of bounds for its `non_witness_utxo`.
+    pub fn try_input(self, input: Input) -> Result<Self, AddInputError> {
+        self.0.check_new_input(&input)?;
+        Ok(self.input(input))
+    }
 }
 
 // Useful if the Creator and Constructor are a single entity.
@@ -146,6 +204,20 @@ impl Constructor<OutputsOnlyModifiable> {
         self.0.output_count += 1;
         self
     }
+
+    /// Adds an output to the PSBT after checking the inputs can fund it.
+    ///
+    /// This is an approximate check to catch gross overspends during construction, the fee is
+    /// ignored i.e., the output amounts may equal the input amounts.
+    ///
+    /// # Errors
+    ///
+    /// If any input does not have a funding UTXO or if, after adding `output`, the sum of the
+    /// output amounts would exceed the sum of the input amounts.
+    pub fn output_with_fee_check(self, output: Output) -> Result<Self, FeeError> {
+        self.0.check_output_affordable(&output)?;
+        Ok(self.output(output))
+    }
 }
 
 // Useful if the Creator and Constructor are a single entity.
@@ -4,6 +4,7 @@
 
 use bitcoin::{absolute, transaction};
 
+use crate::internal_prelude::{BTreeMap, Vec};
 use crate::roles::constructor::{
     Constructor, InputsOnlyModifiable, Modifiable, OutputsOnlyModifiable,
 };
@@ -34,19 +35,58 @@ impl Creator {
             output_count: 0,
             tx_modifiable_flags: 0,
             xpub: BTreeMap::default(),
-            inputs: vec![],
-            outputs: vec![],
+            inputs: Vec::new(),
+            outputs: Vec::new(),
         };
-        psbt.set_inputs_modifiable_flag().set_outputs_modifiable_flag();
+        psbt.set_inputs_modifiable_flag();
+        psbt.set_outputs_modifiable_flag();
         Creator(psbt)
     }
 
+    /// Creates a new PSBT Creator with space pre-allocated for `inputs` inputs and `outputs`
+    /// outputs.
+    ///
+    /// Useful when building large transactions (e.g. consolidations) to avoid reallocating as
+    /// inputs and outputs are added by the [`Constructor`].
+    pub fn with_capacity(inputs: usize, outputs: usize) -> Self {
+        let mut creator = Self::new();
+        creator.0.inputs.reserve_exact(inputs);
+        creator.0.outputs.reserve_exact(outputs);
+        creator
+    }
+
     /// Sets the fallback lock time.
     pub fn fallback_lock_time(mut self, fallback: absolute::LockTime) -> Self {
         self.0.fallback_lock_time = fallback;
         self
     }
 
+    /// Sets or clears the inputs modifiable flag in the transaction modifiable flags.
+    ///
+    /// Inputs are modifiable by default. Useful if the created PSBT is handed to a separate
+    /// Constructor entity, if not use one of the `constructor_foo` functions.
+    pub fn inputs_modifiable(mut self, yes: bool) -> Self {
+        if yes {
+            self.0.set_inputs_modifiable_flag();
+        } else {
+            self.0.clear_inputs_modifiable_flag();
+        }
+        self
+    }
+
+    /// Sets or clears the outputs modifiable flag in the transaction modifiable flags.
+    ///
+    /// Outputs are modifiable by default. Useful if the created PSBT is handed to a separate
+    /// Constructor entity, if not use one of the `constructor_foo` functions.
+    pub fn outputs_modifiable(mut self, yes: bool) -> Self {
+        if yes {
+            self.0.set_outputs_modifiable_flag();
+        } else {
+            self.0.clear_outputs_modifiable_flag();
+        }
+        self
+    }
+
     /// Sets the "has sighash single" flag in then transaction modifiable flags.
     pub fn sighash_single(mut self) -> Self {
         self.0.set_sighash_single_flag();
@@ -68,11 +108,11 @@ impl Creator {
     /// # Examples
     ///
     /// ```
-    /// use psbt_v2::v2::{Creator, Constructor, Modifiable};
+    /// use psbt_v2::{Constructor, Creator, Modifiable};
     ///
     /// // Creator role separate from Constructor role.
-    /// let psbt = Creator::new().psbt();
-    /// let _constructor = Constructor::<Modifiable>::new(psbt);
+    /// let psbt = Creator::new().into_inner();
+    /// let _constructor = Constructor::<Modifiable>::from_psbt(psbt).expect("modifiable");
     ///
     /// // However, since a single entity is likely to be both a Creator and Constructor.
     /// let _constructor = Creator::new().constructor_modifiable();
@@ -89,13 +129,12 @@ impl Creator {
     /// # Examples
     ///
     /// ```
-    /// use psbt_v2::v2::{Creator, Constructor, InputsOnlyModifiable};
+    /// use psbt_v2::{Constructor, Creator, InputsOnlyModifiable};
     ///
     /// // Creator role separate from Constructor role.
-    /// let psbt = Creator::new()
-    ///     .inputs_modifiable()
-    ///     .psbt();
-    /// let _constructor = Constructor::<InputsOnlyModifiable>::new(psbt);
+    /// let psbt = Creator::new().outputs_modifiable(false).into_inner();
+    /// let _constructor =
+    ///     Constructor::<InputsOnlyModifiable>::from_psbt(psbt).expect("inputs modifiable");
     ///
     /// // However, since a single entity is likely to be both a Creator and Constructor.
     /// let _constructor = Creator::new().constructor_inputs_only_modifiable();
@@ -114,13 +153,12 @@ impl Creator {
     /// # Examples
     ///
     /// ```
-    /// use psbt_v2::v2::{Creator, Constructor, OutputsOnlyModifiable};
+    /// use psbt_v2::{Constructor, Creator, OutputsOnlyModifiable};
     ///
     /// // Creator role separate from Constructor role.
-    /// let psbt = Creator::new()
-    ///     .inputs_modifiable()
-    ///     .psbt();
-    /// let _constructor = Constructor::<OutputsOnlyModifiable>::new(psbt);
+    /// let psbt = Creator::new().inputs_modifiable(false).into_inner();
+    /// let _constructor =
+    ///     Constructor::<OutputsOnlyModifiable>::from_psbt(psbt).expect("outputs modifiable");
     ///
     /// // However, since a single entity is likely to be both a Creator and Constructor.
     /// let _constructor = Creator::new().constructor_outputs_only_modifiable();
@@ -16,9 +16,12 @@
 use core::fmt;
 
 use bitcoin::psbt::ExtractTxFeeRateError;
-use bitcoin::{FeeRate, Transaction, Txid};
+use bitcoin::{consensus, Amount, FeeRate, Transaction, Txid};
 
 use crate::error::{write_err, FeeError};
+#[cfg(any(feature = "std", rust_v_1_81))]
+use crate::error::StdError;
+use crate::internal_prelude::{String, Vec};
 use crate::{DetermineLockTimeError, Psbt};
 
 /// Implements the BIP-370 Finalized role.
@@ -33,8 +36,15 @@ impl Extractor {
     ///
     /// An extractor can only accept a PSBT that has been finalized.
     pub fn new(psbt: Psbt) -> Result<Self, ExtractError> {
-        if psbt.inputs.iter().any(|input| !input.is_finalized()) {
-            return Err(ExtractError::PsbtNotFinalized);
+        let not_finalized: Vec<usize> = psbt
+            .inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, input)| !input.is_finalized())
+            .map(|(i, _)| i)
+            .collect();
+        if !not_finalized.is_empty() {
+            return Err(ExtractError::InputsNotFinalized(not_finalized));
         }
         let _ = psbt.determine_lock_time()?;
 
@@ -46,8 +56,13 @@ impl Extractor {
         self.0.id().expect("Extractor guarantees lock time can be determined")
     }
 
+    /// Calculates the fee for the transaction that will be extracted.
+    ///
+    /// See [`Psbt::fee`].
+    pub fn fee(&self) -> Result<Amount, FeeError> { self.0.fee() }
+
     /// An alias for [`Self::extract_tx_fee_rate_limit`].
-    pub fn extract_tx(&self) -> Result<Transaction, ExtractTxFeeRateError> { self.to_psbt_v0().extract_tx() }
+    pub fn extract_tx(&self) -> Result<Transaction, ExtractTxFeeRateError> { self.psbt_v0().extract_tx() }
 
     /// Extracts the [`Transaction`] from a [`Psbt`] by filling in the available signature information.
     ///
@@ -57,7 +72,7 @@ impl Extractor {
     /// that was extracted. These can be extracted from the Errors in order to recover.
     /// See the error documentation for info on the variants. In general, it covers large fees.
     pub fn extract_tx_fee_rate_limit(&self) -> Result<Transaction, ExtractTxFeeRateError> {
-        self.to_psbt_v0().extract_tx_fee_rate_limit()
+        self.psbt_v0().extract_tx_fee_rate_limit()
     }
 
     /// Extracts the [`Transaction`] from a [`Psbt`] by filling in the available signature information.
@@ -65,22 +80,53 @@ impl Extractor {
         &self,
         max_fee_rate: FeeRate,
     ) -> Result<Transaction, ExtractTxFeeRateError> {
-        self.to_psbt_v0().extract_tx_fee_with_rate_limit(max_fee_rate)
+        self.psbt_v0().extract_tx_fee_with_rate_limit(max_fee_rate)
     }
 
     /// Perform [`Self::extract_tx_fee_rate_limit`] without the fee rate check.
     ///
     /// This can result in a transaction with absurdly high fees. Use with caution.
     pub fn extract_tx_unchecked_fee_rate(&self) -> Result<Transaction, ExtractTxError> {
-        self.to_psbt_v0().extract_tx_unchecked_rate_limit()
+        self.psbt_v0().extract_tx_unchecked_rate_limit()
+    }
+
+    /// Extracts the [`Transaction`] and returns its network serialization (consensus encoding).
+    ///
+    /// This is [`Self::extract_tx`] followed by consensus encoding the transaction, ready for
+    /// broadcast.
+    pub fn extract_tx_bytes(&self) -> Result<Vec<u8>, ExtractTxFeeRateError> {
+        let tx = self.extract_tx()?;
+        Ok(consensus::encode::serialize(&tx))
+    }
+
+    /// Extracts the [`Transaction`] and returns its network serialization as a lowercase hex string.
+    pub fn extract_tx_hex(&self) -> Result<String, ExtractTxFeeRateError> {
+        let tx = self.extract_tx()?;
+        Ok(consensus::encode::serialize_hex(&tx))
+    }
+
+    /// Extracts the [`Transaction`] and returns it along with its txid.
+    ///
+    /// The returned txid is the real network txid of the extracted transaction. This is different
+    /// from [`Self::id`] which is the construction time identifier of the PSBT (computed with all
+    /// sequence numbers zeroed) and stays stable while the inputs are being modified.
+    pub fn extract_tx_and_txid(&self) -> Result<(Transaction, Txid), ExtractTxFeeRateError> {
+        let tx = self.extract_tx()?;
+        let txid = tx.compute_txid();
+        Ok((tx, txid))
+    }
+
+    /// Converts the inner PSBT to a `rust-bitcoin` version 0 PSBT.
+    fn psbt_v0(&self) -> bitcoin::Psbt {
+        self.0.clone().to_psbt_v0().expect("Extractor guarantees lock time can be determined")
     }
 }
 
 /// Error constructing an `Extractor`.
 #[derive(Debug)]
 pub enum ExtractError {
-    /// Attempted to extract tx from an unfinalized PSBT.
-    PsbtNotFinalized,
+    /// Attempted to extract tx from an unfinalized PSBT, contains the unfinalized input indices.
+    InputsNotFinalized(Vec<usize>),
     /// Finalizer must be able to determine the lock time.
     DetermineLockTime(DetermineLockTimeError),
 }
@@ -90,21 +136,25 @@ impl fmt::Display for ExtractError {
         use ExtractError::*;
 
         match *self {
-            PsbtNotFinalized => write!(f, "attempted to extract tx from an unfinalized PSBT"),
+            InputsNotFinalized(ref indices) => write!(
+                f,
+                "attempted to extract tx from an unfinalized PSBT, unfinalized inputs: {:?}",
+                indices
+            ),
             DetermineLockTime(ref e) =>
                 write_err!(f, "extractor must be able to determine the lock time"; e),
         }
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for ExtractError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for ExtractError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use ExtractError::*;
 
         match *self {
             DetermineLockTime(ref e) => Some(e),
-            PsbtNotFinalized => None,
+            InputsNotFinalized(_) => None,
         }
     }
 }
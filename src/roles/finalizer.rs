@@ -2,10 +2,21 @@
 
 //! The PSBT Version 2 Finalizer role.
 
+use core::fmt;
+
+use bitcoin::psbt::ExtractTxFeeRateError;
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::sighash::{EcdsaSighashType, NonStandardSighashTypeError};
+use bitcoin::{PublicKey, Txid};
+use bitcoin_internals::write_err;
 use miniscript::psbt::{FinalizeError, PsbtExt};
 
 use crate::error::DetermineLockTimeError;
-use crate::Psbt;
+use crate::internal_prelude::Vec;
+use crate::roles::extractor::ExtractError;
+#[cfg(any(feature = "std", rust_v_1_81))]
+use crate::error::StdError;
+use crate::{InvalidError, Psbt};
 
 /// Implements the BIP-370 Finalizer role.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -16,12 +27,28 @@ impl Finalizer {
     /// Creates an `Finalizer`.
     ///
     /// A finalizer can only be created if all inputs have a funding UTXO.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::FundingUtxo`] with the indices of all inputs that are missing a funding
+    /// UTXO, not just the first one.
     pub fn new(psbt: Psbt) -> Result<Self, Error> {
-        psbt.inptus.iter().all(|input| input.funding_utxo())?;
+        let missing = psbt
+            .inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, input)| input.funding_utxo().is_err())
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(Error::FundingUtxo(missing));
+        }
         let _ = psbt.determine_lock_time()?;
-        psbt.check_partial_sigs_sighash_type()?;
 
-        Ok(Self(psbt))
+        let finalizer = Self(psbt);
+        finalizer.check_partial_sigs_sighash_type()?;
+
+        Ok(finalizer)
     }
 
     /// Returns this PSBT's unique identification.
@@ -34,19 +61,28 @@ impl Finalizer {
         self.0.finalize(secp)
     }
 
+    /// Finalize the PSBT using `rust-miniscript`, returning this crate's [`Psbt`] type.
+    ///
+    /// Same as [`Self::finalize`] but converts the finalized PSBT back to a [`Psbt`] (preserving
+    /// the PSBTv2 fields) so it can be passed directly to an [`crate::Extractor`].
+    pub fn finalize_mut<C: Verification>(
+        self,
+        secp: &Secp256k1<C>,
+    ) -> Result<Psbt, FinalizeMutError> {
+        let psbt = self.finalize(secp)?;
+        Ok(Psbt::from_psbt(psbt)?)
+    }
+
     /// Checks the sighash types of input partial sigs (ECDSA).
     fn check_partial_sigs_sighash_type(
         &self,
     ) -> Result<(), PartialSigsSighashTypeError> {
         use PartialSigsSighashTypeError::*;
 
-        for (input_index, input) in self.inputs.iter().enumerate() {
-            let target_ecdsa_sighash_ty = match input.sighash_type {
-                Some(psbt_hash_ty) => psbt_hash_ty
-                    .ecdsa_hash_ty()
-                    .map_err(|error| NonStandardInputSighashType { input_index, error })?,
-                None => EcdsaSighashType::All,
-            };
+        for (input_index, input) in self.0.inputs.iter().enumerate() {
+            let target_ecdsa_sighash_ty = input
+                .ecdsa_sighash_type()
+                .map_err(|error| NonStandardInputSighashType { input_index, error })?;
 
             for (key, ecdsa_sig) in &input.partial_sigs {
                 let flag = EcdsaSighashType::from_standard(ecdsa_sig.sighash_type as u32)
@@ -68,8 +104,8 @@ impl Finalizer {
 /// Error constructing a [`Finalizer`].
 #[derive(Debug)]
 pub enum Error {
-    /// An input is missing its funding UTXO.
-    FundingUtxo(FundingUtxoError),
+    /// One or more inputs are missing their funding UTXO (contains the input indices).
+    FundingUtxo(Vec<usize>),
     /// Finalizer must be able to determine the lock time.
     DetermineLockTime(DetermineLockTimeError),
     /// An input has incorrect sighash type for its partial sigs (ECDSA).
@@ -82,7 +118,8 @@ impl fmt::Display for Error {
 
         match *self {
             // TODO: Loads of error messages are capitalized, they should not be.
-            FundingUtxo(ref e) => write_err!(f, "Finalizer missing funding UTXO"; e),
+            FundingUtxo(ref indices) =>
+                write!(f, "Finalizer missing funding UTXO for inputs: {:?}", indices),
             DetermineLockTime(ref e) =>
                 write_err!(f, "finalizer must be able to determine the lock time"; e),
             PartialSigsSighashType(ref e) => write_err!(f, "Finalizer sighash type error"; e),
@@ -90,23 +127,19 @@ impl fmt::Display for Error {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for Error {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use Error::*;
 
         match *self {
-            FundingUtxo(ref e) => Some(e),
+            FundingUtxo(_) => None,
             DetermineLockTime(ref e) => Some(e),
             PartialSigsSighashType(ref e) => Some(e),
         }
     }
 }
 
-impl From<FundingUtxoError> for Error {
-    fn from(e: FundingUtxoError) -> Self { Self::FundingUtxo(e) }
-}
-
 impl From<DetermineLockTimeError> for Error {
     fn from(e: DetermineLockTimeError) -> Self { Self::DetermineLockTime(e) }
 }
@@ -115,6 +148,117 @@ impl From<PartialSigsSighashTypeError> for Error {
     fn from(e: PartialSigsSighashTypeError) -> Self { Self::PartialSigsSighashType(e) }
 }
 
+/// Error finalizing a PSBT, see [`Finalizer::finalize_mut`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FinalizeMutError {
+    /// Error finalizing the PSBT.
+    Finalize(FinalizeError),
+    /// The finalized PSBT is not a valid PSBTv2.
+    Invalid(InvalidError),
+}
+
+impl fmt::Display for FinalizeMutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use FinalizeMutError::*;
+
+        match *self {
+            Finalize(ref e) => write_err!(f, "finalize"; e),
+            Invalid(ref e) => write_err!(f, "finalized PSBT is invalid"; e),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for FinalizeMutError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use FinalizeMutError::*;
+
+        match *self {
+            Finalize(ref e) => Some(e),
+            Invalid(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<FinalizeError> for FinalizeMutError {
+    fn from(e: FinalizeError) -> Self { Self::Finalize(e) }
+}
+
+impl From<InvalidError> for FinalizeMutError {
+    fn from(e: InvalidError) -> Self { Self::Invalid(e) }
+}
+
+/// Error finalizing and extracting a transaction, see [`Psbt::finalize_and_extract`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FinalizeExtractError {
+    /// Error constructing the [`Finalizer`].
+    Finalizer(Error),
+    /// Error finalizing the PSBT.
+    Finalize(FinalizeError),
+    /// The finalized PSBT is not a valid PSBTv2.
+    Invalid(InvalidError),
+    /// Error constructing the [`crate::Extractor`].
+    Extractor(ExtractError),
+    /// Error extracting the transaction.
+    ExtractTx(ExtractTxFeeRateError),
+}
+
+impl fmt::Display for FinalizeExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use FinalizeExtractError::*;
+
+        match *self {
+            Finalizer(ref e) => write_err!(f, "finalize and extract"; e),
+            Finalize(ref e) => write_err!(f, "finalize and extract"; e),
+            Invalid(ref e) => write_err!(f, "finalize and extract"; e),
+            Extractor(ref e) => write_err!(f, "finalize and extract"; e),
+            ExtractTx(ref e) => write_err!(f, "finalize and extract"; e),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for FinalizeExtractError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use FinalizeExtractError::*;
+
+        match *self {
+            Finalizer(ref e) => Some(e),
+            Finalize(ref e) => Some(e),
+            Invalid(ref e) => Some(e),
+            Extractor(ref e) => Some(e),
+            ExtractTx(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<Error> for FinalizeExtractError {
+    fn from(e: Error) -> Self { Self::Finalizer(e) }
+}
+
+impl From<FinalizeError> for FinalizeExtractError {
+    fn from(e: FinalizeError) -> Self { Self::Finalize(e) }
+}
+
+impl From<FinalizeMutError> for FinalizeExtractError {
+    fn from(e: FinalizeMutError) -> Self {
+        match e {
+            FinalizeMutError::Finalize(e) => Self::Finalize(e),
+            FinalizeMutError::Invalid(e) => Self::Invalid(e),
+        }
+    }
+}
+
+impl From<ExtractError> for FinalizeExtractError {
+    fn from(e: ExtractError) -> Self { Self::Extractor(e) }
+}
+
+impl From<ExtractTxFeeRateError> for FinalizeExtractError {
+    fn from(e: ExtractTxFeeRateError) -> Self { Self::ExtractTx(e) }
+}
+
 // TODO: Consider creating a type that has input_index and E and simplify all these similar error types?
 /// Error checking the partials sigs have correct sighash types.
 #[derive(Debug)]
@@ -164,9 +308,9 @@ impl fmt::Display for PartialSigsSighashTypeError {
     }
 }
 
-#[cfg(feature = "std")]
-impl std::error::Error for PartialSigsSighashTypeError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for PartialSigsSighashTypeError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         use PartialSigsSighashTypeError::*;
 
         // TODO: Is this correct for a struct error fields?
@@ -177,3 +321,41 @@ impl std::error::Error for PartialSigsSighashTypeError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::{Amount, CompressedPublicKey, OutPoint, ScriptBuf, TxOut};
+
+    use super::*;
+    use crate::output::test_output;
+    use crate::{Constructor, Input, Modifiable};
+
+    /// Returns a PSBT spending a P2WPKH output, the input is not signed.
+    fn unsigned_psbt() -> Psbt {
+        let secp = Secp256k1::signing_only();
+        let sk = SecretKey::from_slice(&[1; 32]).expect("valid secret key");
+        let pk = bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &sk);
+
+        let script_pubkey = ScriptBuf::new_p2wpkh(&CompressedPublicKey(pk).wpubkey_hash());
+        let mut input = Input::new(&OutPoint::COINBASE_PREVOUT);
+        input.witness_utxo = Some(TxOut { value: Amount::from_sat(10_000), script_pubkey });
+
+        let output = test_output(9_000);
+
+        Constructor::<Modifiable>::new()
+            .input(input)
+            .output(output)
+            .into_inner()
+            .expect("valid lock time combination")
+    }
+
+    #[test]
+    fn finalize_mut_unsigned_input() {
+        let secp = Secp256k1::verification_only();
+        let finalizer = Finalizer::new(unsigned_psbt()).expect("all inputs have a funding UTXO");
+
+        let err = finalizer.finalize_mut(&secp).expect_err("input is not signed");
+        assert!(matches!(err, FinalizeMutError::Finalize(_)));
+    }
+}
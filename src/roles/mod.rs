@@ -10,16 +10,26 @@
 //! - The **Signer** role: Use the [`Signer`] type.
 //! - The **Finalizer** role: Use the `Finalizer` type (requires "miniscript" feature).
 //! - The **Extractor** role: Use the [`Extractor`] type.
+//!
+//! To have the compiler enforce the role ordering use a [`Session`].
 
 mod constructor;
 mod creator;
-// mod extractor;
+mod extractor;
 #[cfg(feature = "miniscript")]
-mod finalizer
+mod finalizer;
+mod session;
 mod signer;
 mod updater;
 
 #[allow(unused_imports)] // TODO: Remove this.
-pub use self::{constructor::Constructor, creator::Creator, updater::Updater, signer::Signer, extractor::Extractor};
+pub use self::{
+    constructor::{Constructor, InputsOnlyModifiable, Mod, Modifiable, OutputsOnlyModifiable},
+    creator::Creator,
+    extractor::{ExtractError, Extractor},
+    session::Session,
+    signer::{SighashError, SignError, Signer, SigningErrors, SigningKeys},
+    updater::{Updater, UpdaterError},
+};
 #[cfg(feature = "miniscript")]
-pub use self::finalizer::Finalizer;
+pub use self::finalizer::{Finalizer, FinalizeExtractError, FinalizeMutError};
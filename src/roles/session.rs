@@ -0,0 +1,160 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! A typestate wrapper that enforces the BIP-370 role ordering.
+//!
+//! A [`Session`] moves through the roles in order:
+//!
+//! `Creator → Constructor → Updater → Signer → Finalizer → Extractor`
+//!
+//! The Finalizer stage requires the "miniscript" feature, a PSBT finalized by some other means
+//! can move straight from the Signer to the Extractor stage.
+//!
+//! Each transition consumes the previous stage so roles cannot be carried out out of order. The
+//! individual roles can still be used standalone, use a `Session` if you want the compiler to
+//! guide you through the process.
+
+#[cfg(feature = "miniscript")]
+use bitcoin::secp256k1::{Secp256k1, Verification};
+
+use crate::error::DetermineLockTimeError;
+use crate::roles::constructor::{Constructor, Mod, Modifiable};
+use crate::roles::creator::Creator;
+use crate::roles::extractor::{ExtractError, Extractor};
+#[cfg(feature = "miniscript")]
+use crate::roles::finalizer::{self, FinalizeExtractError, Finalizer};
+use crate::roles::signer::Signer;
+use crate::roles::updater::Updater;
+
+/// A PSBT moving through the BIP-370 roles, `Stage` is the current role.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Session<Stage>(Stage);
+
+impl<Stage> Session<Stage> {
+    /// Returns a reference to the role of the current stage.
+    pub fn stage(&self) -> &Stage { &self.0 }
+
+    /// Returns a mutable reference to the role of the current stage.
+    pub fn stage_mut(&mut self) -> &mut Stage { &mut self.0 }
+
+    /// Applies `f` to the role of the current stage, staying at the current stage.
+    ///
+    /// Useful for the builder style roles e.g., `session.map(|c| c.input(input))`.
+    pub fn map<F: FnOnce(Stage) -> Stage>(self, f: F) -> Self { Self(f(self.0)) }
+
+    /// Returns the role of the current stage, ending the session.
+    pub fn into_inner(self) -> Stage { self.0 }
+}
+
+impl Session<Creator> {
+    /// Starts a new session with a modifiable PSBT with no inputs or outputs.
+    pub fn new() -> Self { Self(Creator::new()) }
+
+    /// Starts a new session from a pre-configured `creator`.
+    pub fn from_creator(creator: Creator) -> Self { Self(creator) }
+
+    /// Moves to the Constructor stage, both inputs and outputs can be added.
+    pub fn constructor(self) -> Session<Constructor<Modifiable>> {
+        Session(self.0.constructor_modifiable())
+    }
+}
+
+impl Default for Session<Creator> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<T: Mod> Session<Constructor<T>> {
+    /// Moves to the Updater stage, no more inputs or outputs can be added.
+    pub fn updater(self) -> Result<Session<Updater>, DetermineLockTimeError> {
+        Ok(Session(self.0.updater()?))
+    }
+}
+
+impl Session<Updater> {
+    /// Moves to the Signer stage.
+    pub fn signer(self) -> Result<Session<Signer>, DetermineLockTimeError> {
+        Ok(Session(Signer::new(self.0.into_inner())?))
+    }
+}
+
+impl Session<Signer> {
+    /// Moves to the Extractor stage, skipping the Finalizer.
+    ///
+    /// For PSBTs finalized without using the [`Finalizer`] role e.g., by an external finalizer.
+    ///
+    /// # Errors
+    ///
+    /// If any input is not finalized.
+    pub fn extractor(self) -> Result<Session<Extractor>, ExtractError> {
+        Ok(Session(Extractor::new(self.0.into_inner())?))
+    }
+}
+
+#[cfg(feature = "miniscript")]
+impl Session<Signer> {
+    /// Moves to the Finalizer stage.
+    pub fn finalizer(self) -> Result<Session<Finalizer>, finalizer::Error> {
+        Ok(Session(Finalizer::new(self.0.into_inner())?))
+    }
+}
+
+#[cfg(feature = "miniscript")]
+impl Session<Finalizer> {
+    /// Finalizes the PSBT using `rust-miniscript` and moves to the Extractor stage.
+    pub fn finalize<C: Verification>(
+        self,
+        secp: &Secp256k1<C>,
+    ) -> Result<Session<Extractor>, FinalizeExtractError> {
+        let psbt = self.0.finalize_mut(secp)?;
+        Ok(Session(Extractor::new(psbt)?))
+    }
+}
+
+impl Session<Extractor> {
+    /// Returns the Extractor, ending the session.
+    ///
+    /// Use the Extractor to extract the finalized transaction e.g., [`Extractor::extract_tx`].
+    pub fn extractor(self) -> Extractor { self.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Amount, OutPoint, ScriptBuf, TxOut, Witness};
+
+    use super::*;
+    use crate::output::test_output;
+    use crate::Input;
+
+    /// Returns a session at the Signer stage spending a single P2WPKH input.
+    fn signer(finalized: bool) -> Session<Signer> {
+        let script_pubkey = ScriptBuf::from_bytes([&[0x00, 0x14][..], &[0x01; 20]].concat());
+        let mut input = Input::new(&OutPoint::COINBASE_PREVOUT);
+        input.witness_utxo = Some(TxOut { value: Amount::from_sat(10_000), script_pubkey });
+        if finalized {
+            input.final_script_sig = Some(ScriptBuf::new());
+            input.final_script_witness = Some(Witness::from_slice(&[vec![0x30; 72], vec![2; 33]]));
+        }
+
+        let output = test_output(9_000);
+
+        Session::new()
+            .constructor()
+            .map(|c| c.input(input).output(output))
+            .updater()
+            .expect("valid lock time combination")
+            .signer()
+            .expect("valid lock time combination")
+    }
+
+    #[test]
+    fn signer_to_extractor() {
+        let session = signer(true).extractor().expect("all inputs finalized");
+        let tx = session.extractor().extract_tx().expect("extract transaction");
+        assert_eq!(tx.input.len(), 1);
+    }
+
+    #[test]
+    fn signer_to_extractor_not_finalized() {
+        let err = signer(false).extractor().expect_err("input is not finalized");
+        assert!(matches!(err, ExtractError::InputsNotFinalized(ref indices) if indices == &[0]));
+    }
+}
@@ -1,9 +1,39 @@
 // SPDX-License-Identifier: CC0-1.0
 
-//! The PSBT Version 2 Updater role.
+//! The PSBT Version 2 Signer role.
 
-use crate::error::DetermineLockTimeError;
-use crate::Psbt;
+use core::fmt;
+
+use bitcoin::bip32::KeySource;
+use bitcoin::hashes::Hash as _;
+use bitcoin::key::{Keypair, TapTweak as _, XOnlyPublicKey};
+use bitcoin::psbt::{GetKey, GetKeyError, KeyRequest};
+use bitcoin::script::{self, PushBytes};
+#[cfg(feature = "rand")]
+use bitcoin::secp256k1::rand::{CryptoRng, RngCore};
+use bitcoin::secp256k1::{schnorr, Message, Parity, Secp256k1, Signing, Verification};
+use bitcoin::sighash::{
+    self, EcdsaSighashType, NonStandardSighashTypeError, Prevouts, SighashCache, TapSighashType,
+};
+use bitcoin::taproot::TapLeafHash;
+use bitcoin::{
+    ecdsa, taproot, transaction, PrivateKey, PublicKey, ScriptBuf, Transaction, TxOut, Txid,
+};
+use bitcoin_internals::write_err;
+
+use crate::error::{DetermineLockTimeError, FundingUtxoError, IndexOutOfBoundsError};
+#[cfg(any(feature = "std", rust_v_1_81))]
+use crate::error::StdError;
+use crate::internal_prelude::{BTreeMap, Vec};
+use crate::{Input, Psbt};
+
+/// A map of input index to the keys used to sign the input.
+pub type SigningKeys = BTreeMap<usize, Vec<PublicKey>>;
+
+/// A map of input index to the error encountered while attempting to sign the input.
+///
+/// Unlike `bitcoin::psbt::SigningErrors` the errors are this crate's [`SignError`].
+pub type SigningErrors = BTreeMap<usize, SignError>;
 
 /// Implements the BIP-370 Signer role.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -32,6 +62,18 @@ impl Signer {
     /// **NOTE**: Taproot inputs are, as yet, not supported by this function. We currently only
     /// attempt to sign ECDSA inputs.
     ///
+    /// Keys that already have a signature in `partial_sigs` are not used to sign again and inputs
+    /// that already have a signature for every key `k` controls are skipped without computing
+    /// their sighash, this avoids redundant signing work. Previously all inputs were signed and
+    /// existing signatures overwritten, use [`Self::sign_all`] with `force` set to get that
+    /// behaviour.
+    ///
+    /// Inputs that `k` does not control are skipped, they never cause an error even if they are
+    /// missing data required to compute their sighash.
+    ///
+    /// An error returned by `k` is reported as [`SignError::GetKey`] for the input, only a key `k`
+    /// does not have ([`GetKeyError::NotSupported`] or `Ok(None)`) causes the key to be skipped.
+    ///
     /// If you just want to sign an input with one specific key consider using `sighash_ecdsa`. This
     /// function does not support scripts that contain `OP_CODESEPARATOR`.
     ///
@@ -39,10 +81,16 @@ impl Signer {
     ///
     /// Either Ok(SigningKeys) or Err((SigningKeys, SigningErrors)), where
     /// - SigningKeys: A map of input index -> pubkey associated with secret key used to sign.
-    /// - SigningKeys: A map of input index -> the error encountered while attempting to sign.
+    /// - SigningErrors: A map of input index -> the error encountered while attempting to sign.
+    ///
+    /// Since version 0.3.0 these are this crate's [`SigningKeys`] and [`SigningErrors`] types,
+    /// previously the errors were the upstream `bitcoin::psbt::SignError`.
     ///
-    /// If an error is returned some signatures may already have been added to the PSBT. Since
-    /// `partial_sigs` is a [`BTreeMap`] it is safe to retry, previous sigs will be overwritten.
+    /// P2SH wrapped segwit inputs (P2SH-P2WPKH and P2SH-P2WSH) are signed against the inner
+    /// witness program and have their `final_script_sig` set, see [`Self::sign_input`].
+    ///
+    /// If an error is returned some signatures may already have been added to the PSBT. It is safe
+    /// to retry, inputs signed by the previous attempt are skipped.
     pub fn sign<C, K>(
         self,
         k: &K,
@@ -50,12 +98,98 @@ impl Signer {
     ) -> Result<(Psbt, SigningKeys), (SigningKeys, SigningErrors)>
     where
         C: Signing,
-        K: GetKey,
+        K: GetKey<Error = GetKeyError>,
     {
-        let tx = self.unsigned_tx();
-        let mut psbt = self.psbt();
+        self.sign_all(k, secp, false)
+    }
+
+    /// Attempts to create _all_ the required signatures for this PSBT using `k`.
+    ///
+    /// Same as [`Self::sign`] but if `force` is true every input is signed, even if it already has
+    /// a signature for every key `k` controls. Since `partial_sigs` is a [`BTreeMap`] previous
+    /// signatures are overwritten.
+    pub fn sign_all<C, K>(
+        self,
+        k: &K,
+        secp: &Secp256k1<C>,
+        force: bool,
+    ) -> Result<(Psbt, SigningKeys), (SigningKeys, SigningErrors)>
+    where
+        C: Signing,
+        K: GetKey<Error = GetKeyError>,
+    {
+        self.sign_inputs(k, secp, force, |_, _| true)
+    }
+
+    /// Attempts to create the required signatures for the inputs selected by `filter` using `k`.
+    ///
+    /// Same as [`Self::sign`] but only inputs for which `filter` returns true are signed. All other
+    /// inputs are not touched, no sighash is computed for them, and they do not appear in the
+    /// returned signing keys or errors.
+    ///
+    /// For example, to only sign inputs derived from a specific master key use
+    /// `|_, input| input.keys_for_fingerprint(fp).next().is_some()`.
+    pub fn sign_matching<C, K, F>(
+        self,
+        k: &K,
+        secp: &Secp256k1<C>,
+        filter: F,
+    ) -> Result<(Psbt, SigningKeys), (SigningKeys, SigningErrors)>
+    where
+        C: Signing,
+        K: GetKey<Error = GetKeyError>,
+        F: Fn(usize, &Input) -> bool,
+    {
+        self.sign_inputs(k, secp, false, filter)
+    }
 
-        psbt.sign(tx, k, secp).map(|signing_keys| (psbt, signing_keys))
+    /// Signs every ECDSA input selected by `filter`, inputs not selected are never touched.
+    fn sign_inputs<C, K, F>(
+        self,
+        k: &K,
+        secp: &Secp256k1<C>,
+        force: bool,
+        filter: F,
+    ) -> Result<(Psbt, SigningKeys), (SigningKeys, SigningErrors)>
+    where
+        C: Signing,
+        K: GetKey<Error = GetKeyError>,
+        F: Fn(usize, &Input) -> bool,
+    {
+        let mut signer = self;
+        let mut used = SigningKeys::new();
+        let mut errors = SigningErrors::new();
+
+        for input_index in 0..signer.0.inputs.len() {
+            let input = &signer.0.inputs[input_index];
+            if !filter(input_index, input) {
+                continue;
+            }
+            let is_taproot =
+                input.funding_utxo().map(|utxo| utxo.script_pubkey.is_p2tr()).unwrap_or(false);
+            if is_taproot {
+                continue;
+            }
+
+            match signer.sign_ecdsa_input(input_index, k, secp, force) {
+                // The input was already signed by every key `k` controls.
+                Ok(keys) if keys.is_empty() => {}
+                Ok(keys) => {
+                    used.insert(input_index, keys);
+                }
+                // Not an error, `k` just does not control any of the input's keys.
+                Err(SignError::KeyNotFound { .. }) => {}
+                Err(e) => {
+                    errors.insert(input_index, e);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok((signer.0, used))
+        } else {
+            Err((used, errors))
+        }
     }
 
     /// Sets the PSBT_GLOBAL_TX_MODIFIABLE as required after signing an ECDSA input.
@@ -66,6 +200,901 @@ impl Signer {
         self.0.clear_tx_modifiable(ty as u8)
     }
 
+    /// Returns the keys, per input, that are declared but have not yet produced a signature.
+    ///
+    /// Declared keys are those in `bip32_derivation` (satisfied by an entry in `partial_sigs`) and
+    /// `tap_key_origins` (satisfied by `tap_key_sig` if the key is the internal key, or by an entry
+    /// in `tap_script_sigs`). Taproot keys are x-only so are returned with even parity.
+    ///
+    /// Inputs with no missing signatures are not included in the returned map. Note this is
+    /// best-effort, the PSBT does not declare the full signing policy so not all declared keys
+    /// may be needed to satisfy an input.
+    pub fn remaining_signatures(&self) -> BTreeMap<usize, Vec<PublicKey>> {
+        let mut remaining = BTreeMap::new();
+
+        for (input_index, input) in self.0.inputs.iter().enumerate() {
+            let ecdsa = input
+                .bip32_derivation
+                .keys()
+                .filter(|pk| !input.partial_sigs.keys().any(|key| key.inner == **pk))
+                .map(|pk| PublicKey::new(*pk));
+
+            let taproot = input
+                .tap_key_origins
+                .keys()
+                .filter(|xonly| {
+                    let key_sig =
+                        input.tap_internal_key == Some(**xonly) && input.tap_key_sig.is_some();
+                    let script_sig = input.tap_script_sigs.keys().any(|(key, _)| key == *xonly);
+                    !(key_sig || script_sig)
+                })
+                .map(|xonly| PublicKey::new(xonly.public_key(Parity::Even)));
+
+            let missing = ecdsa.chain(taproot).collect::<Vec<_>>();
+            if !missing.is_empty() {
+                remaining.insert(input_index, missing);
+            }
+        }
+        remaining
+    }
+
+    /// Signs the ECDSA input at `input_index` with every key `k` can provide for it.
+    ///
+    /// Keys are requested for each entry in the input's `bip32_derivation` map, both by key source
+    /// and by public key. The PSBT_GLOBAL_TX_MODIFIABLE flags are updated after signing.
+    ///
+    /// A signature is created for _every_ key `k` controls, not just the first one found. For
+    /// example, if `k` holds two of the keys of a 2-of-3 multisig input then two `partial_sigs`
+    /// entries are added. A key returned by `k` that does not match the public key it was
+    /// requested for is ignored.
+    ///
+    /// Legacy, segwit v0 and P2SH wrapped segwit v0 inputs are supported. For P2SH wrapped inputs
+    /// the sighash is computed against the `redeem_script` (P2SH-P2WPKH) or the `witness_script`
+    /// (P2SH-P2WSH) rather than the P2SH script pubkey. The scriptSig of a P2SH wrapped input
+    /// only pushes the `redeem_script` so `final_script_sig` is set, the witness is left to the
+    /// Finalizer.
+    ///
+    /// # Returns
+    ///
+    /// The public keys associated with the secret keys used to sign.
+    pub fn sign_input<C, K>(
+        &mut self,
+        input_index: usize,
+        k: &K,
+        secp: &Secp256k1<C>,
+    ) -> Result<Vec<PublicKey>, SignError>
+    where
+        C: Signing,
+        K: GetKey<Error = GetKeyError>,
+    {
+        self.sign_ecdsa_input(input_index, k, secp, true)
+    }
+
+    /// Signs the ECDSA input at `input_index`, see [`Self::sign_input`].
+    ///
+    /// Keys are only derived once. If `force` is false keys that already have a signature in
+    /// `partial_sigs` are not used to sign again, if no key needs to sign the sighash is not
+    /// computed and an empty list of keys is returned.
+    fn sign_ecdsa_input<C, K>(
+        &mut self,
+        input_index: usize,
+        k: &K,
+        secp: &Secp256k1<C>,
+        force: bool,
+    ) -> Result<Vec<PublicKey>, SignError>
+    where
+        C: Signing,
+        K: GetKey<Error = GetKeyError>,
+    {
+        let input = self.0.checked_input(input_index)?;
+
+        let mut controlled = false;
+        let mut keys = Vec::new();
+        for (derivation_pk, key_source) in &input.bip32_derivation {
+            let pubkey = PublicKey::new(*derivation_pk);
+            let sk = match get_ecdsa_key(k, key_source, pubkey, secp) {
+                Ok(Some(sk)) => sk,
+                Ok(None) => continue,
+                Err(error) => return Err(SignError::GetKey { input_index, error }),
+            };
+
+            let pk = sk.public_key(secp);
+            if pk.inner != *derivation_pk {
+                continue;
+            }
+            controlled = true;
+            if force || !input.partial_sigs.contains_key(&pk) {
+                keys.push((pk, sk));
+            }
+        }
+
+        if !controlled {
+            return Err(SignError::KeyNotFound { input_index });
+        }
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let tx = self.0.unsigned_tx()?;
+        let mut cache = SighashCache::new(&tx);
+        let (msg, ty) = self.0.ecdsa_sighash(input_index, &mut cache)?;
+
+        let input = self.0.checked_input_mut(input_index)?;
+        let mut used = Vec::with_capacity(keys.len());
+        for (pk, sk) in keys {
+            let signature = secp.sign_ecdsa(&msg, &sk.inner);
+            input.partial_sigs.insert(pk, ecdsa::Signature { signature, sighash_type: ty });
+            used.push(pk);
+        }
+        if let Some(script_sig) = wrapped_segwit_script_sig(input) {
+            input.final_script_sig = Some(script_sig);
+        }
+
+        self.ecdsa_clear_tx_modifiable(ty);
+        Ok(used)
+    }
+
+    /// Signs the taproot input at `input_index` with every key `k` can provide for it.
+    ///
+    /// Keys are requested for each entry in the input's `tap_key_origins` map. If the key is the
+    /// internal key a key path signature is created (tweaked with `tap_merkle_root`), a script
+    /// path signature is created for each leaf hash associated with the key. Signing uses
+    /// deterministic BIP-340 nonces (no auxiliary randomness), this is the default and is safe.
+    /// See `Self::sign_taproot_with_aux_rand` (requires the "rand" feature) to use auxiliary
+    /// randomness.
+    ///
+    /// # Returns
+    ///
+    /// The x-only public keys associated with the secret keys used to sign.
+    pub fn sign_taproot<C, K>(
+        &mut self,
+        input_index: usize,
+        k: &K,
+        secp: &Secp256k1<C>,
+    ) -> Result<Vec<XOnlyPublicKey>, SignError>
+    where
+        C: Signing + Verification,
+        K: GetKey<Error = GetKeyError>,
+    {
+        self.sign_taproot_with(input_index, k, secp, |msg, keypair| {
+            secp.sign_schnorr_no_aux_rand(msg, keypair)
+        })
+    }
+
+    /// Same as [`Self::sign_taproot`] but passes fresh auxiliary randomness from `rng` into each
+    /// BIP-340 signing operation.
+    ///
+    /// Auxiliary randomness adds resistance to side-channel attacks, only use this if you have a
+    /// hardened cryptographically secure RNG. The deterministic [`Self::sign_taproot`] is the
+    /// default.
+    #[cfg(feature = "rand")]
+    pub fn sign_taproot_with_aux_rand<C, K, R>(
+        &mut self,
+        input_index: usize,
+        k: &K,
+        secp: &Secp256k1<C>,
+        rng: &mut R,
+    ) -> Result<Vec<XOnlyPublicKey>, SignError>
+    where
+        C: Signing + Verification,
+        K: GetKey<Error = GetKeyError>,
+        R: RngCore + CryptoRng,
+    {
+        self.sign_taproot_with(input_index, k, secp, |msg, keypair| {
+            let mut aux_rand = [0u8; 32];
+            rng.fill_bytes(&mut aux_rand);
+            secp.sign_schnorr_with_aux_rand(msg, keypair, &aux_rand)
+        })
+    }
+
+    /// Signs the taproot input at `input_index`, creating each Schnorr signature with `sign`.
+    fn sign_taproot_with<C, K, F>(
+        &mut self,
+        input_index: usize,
+        k: &K,
+        secp: &Secp256k1<C>,
+        mut sign: F,
+    ) -> Result<Vec<XOnlyPublicKey>, SignError>
+    where
+        C: Signing + Verification,
+        K: GetKey<Error = GetKeyError>,
+        F: FnMut(&Message, &Keypair) -> schnorr::Signature,
+    {
+        let input = self.0.checked_input(input_index)?;
+        let tx = self.0.unsigned_tx()?;
+        let mut cache = SighashCache::new(&tx);
+
+        let mut key_sig = None;
+        let mut script_sigs = Vec::new();
+        let mut used = Vec::new();
+
+        for (xonly, (leaf_hashes, key_source)) in &input.tap_key_origins {
+            let sk = match k.get_key(KeyRequest::Bip32(key_source.clone()), secp) {
+                Ok(Some(sk)) => sk,
+                Ok(None) | Err(GetKeyError::NotSupported) => continue,
+                Err(error) => return Err(SignError::GetKey { input_index, error }),
+            };
+            let keypair = Keypair::from_secret_key(secp, &sk.inner);
+            if keypair.x_only_public_key().0 != *xonly {
+                continue;
+            }
+
+            if input.tap_internal_key == Some(*xonly) {
+                let (msg, ty) = self.0.taproot_sighash(input_index, None, &mut cache)?;
+                let tweaked = keypair.tap_tweak(secp, input.tap_merkle_root);
+                let signature = sign(&msg, &tweaked.to_inner());
+                key_sig = Some(taproot::Signature { signature, sighash_type: ty });
+            }
+
+            for leaf_hash in leaf_hashes {
+                let (msg, ty) = self.0.taproot_sighash(input_index, Some(*leaf_hash), &mut cache)?;
+                let signature = sign(&msg, &keypair);
+                let signature = taproot::Signature { signature, sighash_type: ty };
+                script_sigs.push(((*xonly, *leaf_hash), signature));
+            }
+            used.push(*xonly);
+        }
+
+        if used.is_empty() {
+            return Err(SignError::KeyNotFound { input_index });
+        }
+
+        let input = self.0.checked_input_mut(input_index)?;
+        if key_sig.is_some() {
+            input.tap_key_sig = key_sig;
+        }
+        input.tap_script_sigs.extend(script_sigs);
+        let ty = input.taproot_sighash_type();
+
+        self.0.clear_tx_modifiable(ty as u8);
+        Ok(used)
+    }
+
+    /// Computes the sighash message digest for each input without signing.
+    ///
+    /// This decouples digest computation from key custody, useful for auditing and for hardware
+    /// wallet confirmation flows. ECDSA inputs (legacy, segwit v0 and P2SH wrapped segwit v0) use
+    /// the input's sighash type or `SIGHASH_ALL` if not set. Taproot inputs use the input's
+    /// sighash type or `SIGHASH_DEFAULT` if not set, the digest is for a key path spend.
+    ///
+    /// # Returns
+    ///
+    /// A map of input index -> sighash message digest.
+    pub fn sighashes(&self) -> Result<BTreeMap<usize, Vec<u8>>, SighashError> {
+        let tx = self.unsigned_tx();
+        let mut cache = SighashCache::new(&tx);
+
+        let mut sighashes = BTreeMap::new();
+        for (input_index, input) in self.0.inputs.iter().enumerate() {
+            let utxo = input
+                .funding_utxo()
+                .map_err(|error| SighashError::FundingUtxo { input_index, error })?;
+            let msg = if utxo.script_pubkey.is_p2tr() {
+                self.0.taproot_sighash(input_index, None, &mut cache)?.0
+            } else {
+                self.0.ecdsa_sighash(input_index, &mut cache)?.0
+            };
+            sighashes.insert(input_index, msg.as_ref().to_vec());
+        }
+        Ok(sighashes)
+    }
+
     /// Returns the inner [`Psbt`].
     pub fn into_inner(self) -> Psbt { self.0 }
 }
+
+impl Psbt {
+    /// Computes the ECDSA sighash message for the input at `input_index`.
+    ///
+    /// Supports legacy, segwit v0 and P2SH wrapped segwit v0 inputs. The digest is computed using
+    /// the input's funding UTXO, redeem and witness scripts, and its sighash type (or
+    /// `SIGHASH_ALL` if not set). This does not require a key so can be used to drive an external
+    /// signer (e.g. an HSM), `cache` must be created from the PSBT's unsigned transaction.
+    pub fn ecdsa_sighash(
+        &self,
+        input_index: usize,
+        cache: &mut SighashCache<&Transaction>,
+    ) -> Result<(Message, EcdsaSighashType), SighashError> {
+        use SighashError::*;
+
+        let input = self.checked_input(input_index)?;
+        let utxo = input.funding_utxo().map_err(|error| FundingUtxo { input_index, error })?;
+        let spk = &utxo.script_pubkey;
+
+        let ty = input
+            .ecdsa_sighash_type()
+            .map_err(|error| NonStandardSighashType { input_index, error })?;
+
+        // P2SH wrapped segwit signs against the inner witness program.
+        let script = if spk.is_p2sh() {
+            input.redeem_script.as_ref().ok_or(MissingRedeemScript { input_index })?
+        } else {
+            spk
+        };
+
+        let digest = if script.is_p2wpkh() {
+            cache
+                .p2wpkh_signature_hash(input_index, script, utxo.value, ty)
+                .map_err(|error| P2wpkh { input_index, error })?
+                .to_byte_array()
+        } else if script.is_p2wsh() {
+            let witness_script =
+                input.witness_script.as_ref().ok_or(MissingWitnessScript { input_index })?;
+            cache
+                .p2wsh_signature_hash(input_index, witness_script, utxo.value, ty)
+                .map_err(InputsIndex)?
+                .to_byte_array()
+        } else {
+            cache
+                .legacy_signature_hash(input_index, script, ty.to_u32())
+                .map_err(InputsIndex)?
+                .to_byte_array()
+        };
+        Ok((Message::from_digest(digest), ty))
+    }
+
+    /// Computes the taproot sighash message for the input at `input_index`.
+    ///
+    /// Computes the key path spend sighash if `leaf_hash` is `None`, otherwise the script path
+    /// spend sighash for the leaf. Uses the input's sighash type or `SIGHASH_DEFAULT` if not set,
+    /// as for [`Psbt::ecdsa_sighash`] `cache` must be created from the unsigned transaction.
+    pub fn taproot_sighash(
+        &self,
+        input_index: usize,
+        leaf_hash: Option<TapLeafHash>,
+        cache: &mut SighashCache<&Transaction>,
+    ) -> Result<(Message, TapSighashType), SighashError> {
+        use SighashError::*;
+
+        let input = self.checked_input(input_index)?;
+        let ty = match input.sighash_type {
+            Some(ty) =>
+                ty.taproot_hash_ty().map_err(|_| InvalidTaprootSighashType { input_index })?,
+            None => TapSighashType::Default,
+        };
+
+        let prevouts = self.prevouts()?;
+        let prevouts = Prevouts::All(&prevouts);
+        let sighash = match leaf_hash {
+            None => cache.taproot_key_spend_signature_hash(input_index, &prevouts, ty),
+            Some(leaf_hash) =>
+                cache.taproot_script_spend_signature_hash(input_index, &prevouts, leaf_hash, ty),
+        }
+        .map_err(|error| Taproot { input_index, error })?;
+
+        Ok((Message::from_digest(sighash.to_byte_array()), ty))
+    }
+
+    /// Returns the funding UTXOs of all inputs, in input order.
+    fn prevouts(&self) -> Result<Vec<&TxOut>, SighashError> {
+        use SighashError::FundingUtxo;
+
+        self.inputs
+            .iter()
+            .enumerate()
+            .map(|(input_index, input)| {
+                input.funding_utxo().map_err(|error| FundingUtxo { input_index, error })
+            })
+            .collect()
+    }
+}
+
+/// Requests the secret key for `pubkey` from `k`, first by `key_source` then by public key.
+///
+/// A request the key store does not support is not an error, `Ok(None)` is only returned if `k`
+/// supports neither request or does not have the key.
+fn get_ecdsa_key<C, K>(
+    k: &K,
+    key_source: &KeySource,
+    pubkey: PublicKey,
+    secp: &Secp256k1<C>,
+) -> Result<Option<PrivateKey>, GetKeyError>
+where
+    C: Signing,
+    K: GetKey<Error = GetKeyError>,
+{
+    match k.get_key(KeyRequest::Bip32(key_source.clone()), secp) {
+        Ok(Some(sk)) => return Ok(Some(sk)),
+        Ok(None) | Err(GetKeyError::NotSupported) => {}
+        Err(e) => return Err(e),
+    }
+    match k.get_key(KeyRequest::Pubkey(pubkey), secp) {
+        Err(GetKeyError::NotSupported) => Ok(None),
+        res => res,
+    }
+}
+
+/// Returns the scriptSig of a P2SH wrapped segwit input, `None` for any other input.
+fn wrapped_segwit_script_sig(input: &Input) -> Option<ScriptBuf> {
+    let script_pubkey = &input.funding_utxo().ok()?.script_pubkey;
+    let redeem_script = input.redeem_script.as_ref()?;
+    if !script_pubkey.is_p2sh() || !redeem_script.is_witness_program() {
+        return None;
+    }
+
+    let bytes = <&PushBytes>::try_from(redeem_script.as_bytes())
+        .expect("a witness program is at most 42 bytes");
+    Some(script::Builder::new().push_slice(bytes).into_script())
+}
+
+/// Error computing the sighash message digest for an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SighashError {
+    /// Unable to get the funding UTXO for this input.
+    FundingUtxo {
+        /// The input index.
+        input_index: usize,
+        /// The funding UTXO error.
+        error: FundingUtxoError,
+    },
+    /// Non-standard sighash type found in `input.sighash_type` field.
+    NonStandardSighashType {
+        /// The input index with the non-standard sighash type.
+        input_index: usize,
+        /// The non-standard sighash type error.
+        error: NonStandardSighashTypeError,
+    },
+    /// The `input.sighash_type` field is not a valid taproot sighash type.
+    InvalidTaprootSighashType {
+        /// The input index with the invalid sighash type.
+        input_index: usize,
+    },
+    /// Input spends a P2SH output but has no redeem script.
+    MissingRedeemScript {
+        /// The input index.
+        input_index: usize,
+    },
+    /// Input spends a P2WSH output but has no witness script.
+    MissingWitnessScript {
+        /// The input index.
+        input_index: usize,
+    },
+    /// Error computing a P2WPKH sighash.
+    P2wpkh {
+        /// The input index.
+        input_index: usize,
+        /// The P2WPKH sighash error.
+        error: sighash::P2wpkhError,
+    },
+    /// Error computing a taproot sighash.
+    Taproot {
+        /// The input index.
+        input_index: usize,
+        /// The taproot sighash error.
+        error: sighash::TaprootError,
+    },
+    /// Input index out of bounds for the unsigned transaction.
+    InputsIndex(transaction::InputsIndexError),
+    /// The input index is out of bounds.
+    IndexOutOfBounds(IndexOutOfBoundsError),
+}
+
+impl fmt::Display for SighashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SighashError::*;
+
+        match *self {
+            FundingUtxo { input_index, ref error } =>
+                write_err!(f, "unable to get funding utxo for input {}", input_index; error),
+            NonStandardSighashType { input_index, ref error } =>
+                write_err!(f, "non-standard sighash type for input {}", input_index; error),
+            InvalidTaprootSighashType { input_index } =>
+                write!(f, "invalid taproot sighash type for input {}", input_index),
+            MissingRedeemScript { input_index } =>
+                write!(f, "missing redeem script for P2SH input {}", input_index),
+            MissingWitnessScript { input_index } =>
+                write!(f, "missing witness script for P2WSH input {}", input_index),
+            P2wpkh { input_index, ref error } =>
+                write_err!(f, "P2WPKH sighash for input {}", input_index; error),
+            Taproot { input_index, ref error } =>
+                write_err!(f, "taproot sighash for input {}", input_index; error),
+            InputsIndex(ref e) => write_err!(f, "sighash"; e),
+            IndexOutOfBounds(ref e) => write_err!(f, "sighash"; e),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for SighashError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use SighashError::*;
+
+        match *self {
+            FundingUtxo { ref error, .. } => Some(error),
+            NonStandardSighashType { ref error, .. } => Some(error),
+            P2wpkh { ref error, .. } => Some(error),
+            Taproot { ref error, .. } => Some(error),
+            InputsIndex(ref e) => Some(e),
+            IndexOutOfBounds(ref e) => Some(e),
+            InvalidTaprootSighashType { .. }
+            | MissingRedeemScript { .. }
+            | MissingWitnessScript { .. } => None,
+        }
+    }
+}
+
+impl From<IndexOutOfBoundsError> for SighashError {
+    fn from(e: IndexOutOfBoundsError) -> Self { Self::IndexOutOfBounds(e) }
+}
+
+/// Error signing an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SignError {
+    /// Unable to determine the lock time.
+    DetermineLockTime(DetermineLockTimeError),
+    /// The input index is out of bounds.
+    IndexOutOfBounds(IndexOutOfBoundsError),
+    /// Unable to compute the sighash.
+    Sighash(SighashError),
+    /// No signing key found for the input.
+    KeyNotFound {
+        /// The input index.
+        input_index: usize,
+    },
+    /// The key store failed to provide a signing key.
+    GetKey {
+        /// The input index.
+        input_index: usize,
+        /// The error returned by the key store.
+        error: GetKeyError,
+    },
+}
+
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SignError::*;
+
+        match *self {
+            DetermineLockTime(ref e) => write_err!(f, "sign"; e),
+            IndexOutOfBounds(ref e) => write_err!(f, "sign"; e),
+            Sighash(ref e) => write_err!(f, "sign"; e),
+            KeyNotFound { input_index } =>
+                write!(f, "no signing key found for input {}", input_index),
+            GetKey { input_index, ref error } =>
+                write_err!(f, "failed to get signing key for input {}", input_index; error),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for SignError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use SignError::*;
+
+        match *self {
+            DetermineLockTime(ref e) => Some(e),
+            IndexOutOfBounds(ref e) => Some(e),
+            Sighash(ref e) => Some(e),
+            GetKey { ref error, .. } => Some(error),
+            KeyNotFound { .. } => None,
+        }
+    }
+}
+
+impl From<DetermineLockTimeError> for SignError {
+    fn from(e: DetermineLockTimeError) -> Self { Self::DetermineLockTime(e) }
+}
+
+impl From<IndexOutOfBoundsError> for SignError {
+    fn from(e: IndexOutOfBoundsError) -> Self { Self::IndexOutOfBounds(e) }
+}
+
+impl From<SighashError> for SignError {
+    fn from(e: SighashError) -> Self { Self::Sighash(e) }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::bip32::{DerivationPath, Fingerprint, KeySource};
+    use bitcoin::hex::FromHex;
+    use bitcoin::opcodes::all::{OP_CHECKMULTISIG, OP_CHECKSIG};
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::{
+        absolute, consensus, Amount, CompressedPublicKey, Network, OutPoint, PrivateKey, TxIn,
+    };
+
+    use super::*;
+    use crate::output::test_output;
+    use crate::{Constructor, InputBuilder, Modifiable, Output};
+
+    fn private_key(byte: u8) -> PrivateKey {
+        let sk = SecretKey::from_slice(&[byte; 32]).expect("valid secret key");
+        PrivateKey::new(sk, Network::Bitcoin)
+    }
+
+    fn key_source(child: u32) -> KeySource {
+        let path = format!("m/84'/0'/0'/0/{}", child);
+        let path = DerivationPath::from_str(&path).expect("valid derivation path");
+        (Fingerprint::from([0xd3, 0x4d, 0xb3, 0x3f]), path)
+    }
+
+    /// Creates a PSBT spending `input` to a single output.
+    fn psbt(input: Input) -> Psbt {
+        Constructor::<Modifiable>::new()
+            .input(input)
+            .output(test_output(90_000))
+            .into_inner()
+            .expect("valid lock time combination")
+    }
+
+    /// Returns a script that checks a single signature for `pk`.
+    fn checksig_script(pk: &PublicKey) -> ScriptBuf {
+        script::Builder::new().push_key(pk).push_opcode(OP_CHECKSIG).into_script()
+    }
+
+    /// Asserts input 0 has sighash `want` and that the signer signs it with `sk`.
+    ///
+    /// Returns the signed PSBT.
+    fn assert_signs_input(psbt: Psbt, sk: PrivateKey, want: Message) -> Psbt {
+        let secp = Secp256k1::new();
+        let pk = sk.public_key(&secp);
+
+        let tx = psbt.unsigned_tx().expect("valid lock time combination");
+        let mut cache = SighashCache::new(&tx);
+        let (msg, ty) = psbt.ecdsa_sighash(0, &mut cache).expect("sighash");
+        assert_eq!(msg, want);
+        assert_eq!(ty, EcdsaSighashType::All);
+
+        let k = [(pk, sk)].into_iter().collect::<BTreeMap<_, _>>();
+        let signer = Signer::new(psbt).expect("valid lock time combination");
+        let (psbt, keys) = signer.sign(&k, &secp).expect("signing succeeds");
+        assert_eq!(keys[&0], [pk]);
+
+        let sig = psbt.inputs[0].partial_sigs.get(&pk).expect("input 0 signed with pk");
+        secp.verify_ecdsa(&want, &sig.signature, &pk.inner).expect("valid signature");
+        psbt
+    }
+
+    /// Asserts every partial signature on input `index` is valid for the input's sighash.
+    fn assert_partial_sigs_valid(psbt: &Psbt, index: usize) {
+        let secp = Secp256k1::verification_only();
+        let tx = psbt.unsigned_tx().expect("valid lock time combination");
+        let mut cache = SighashCache::new(&tx);
+        let (msg, _) = psbt.ecdsa_sighash(index, &mut cache).expect("sighash");
+
+        for (pk, sig) in &psbt.inputs[index].partial_sigs {
+            secp.verify_ecdsa(&msg, &sig.signature, &pk.inner).expect("valid signature");
+        }
+    }
+
+    #[test]
+    fn sign_multisig_with_every_controlled_key() {
+        let secp = Secp256k1::new();
+        let sks = [private_key(1), private_key(2), private_key(3)];
+        let pks = sks.iter().map(|sk| sk.public_key(&secp)).collect::<Vec<_>>();
+
+        let witness_script = script::Builder::new()
+            .push_int(2)
+            .push_key(&pks[0])
+            .push_key(&pks[1])
+            .push_key(&pks[2])
+            .push_int(3)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let utxo = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: ScriptBuf::new_p2wsh(&witness_script.wscript_hash()),
+        };
+
+        let mut builder = InputBuilder::new(&OutPoint::COINBASE_PREVOUT)
+            .witness_utxo(utxo)
+            .witness_script(witness_script);
+        for (child, pk) in pks.iter().enumerate() {
+            builder = builder.bip32_derivation(pk.inner, key_source(child as u32));
+        }
+        let psbt = psbt(builder.build().expect("valid input"));
+
+        // `k` controls two of the three multisig keys.
+        let k = sks[..2].iter().map(|sk| (sk.public_key(&secp), *sk)).collect::<BTreeMap<_, _>>();
+        let signer = Signer::new(psbt).expect("valid lock time combination");
+        let (psbt, keys) = signer.sign(&k, &secp).expect("signing succeeds");
+
+        let partial_sigs = &psbt.inputs[0].partial_sigs;
+        assert_eq!(partial_sigs.len(), 2);
+        assert!(partial_sigs.contains_key(&pks[0]));
+        assert!(partial_sigs.contains_key(&pks[1]));
+        assert_partial_sigs_valid(&psbt, 0);
+
+        let used = keys.get(&0).expect("input 0 was signed");
+        assert_eq!(used.len(), 2);
+        assert!(used.contains(&pks[0]));
+        assert!(used.contains(&pks[1]));
+        assert!(!used.contains(&pks[2]));
+    }
+
+    #[test]
+    fn sign_p2sh_p2wpkh_bip143_vector() {
+        // The P2SH-P2WPKH example from BIP-143.
+        const TX: &str = "0100000001db6b1b20aa0fd7b23880be2ecbd4a98130974cf4748fb66092ac4d3ceb1a54\
+                          770100000000feffffff02b8b4eb0b000000001976a914a457b684d7f0d539a46a45bbc0\
+                          43f35b59d0d96388ac0008af2f000000001976a914fd270b1ee6abcaea97fea7ad0402e8\
+                          bd8ad6d77c88ac92040000";
+        const SK: &str = "eb696a065ef48a2192da5b28b694f87544b30fae8327c4510137a922f32c6dcf";
+        const REDEEM_SCRIPT: &str = "001479091972186c449eb1ded22b78e40d009bdf0089";
+        const SIGHASH: &str = "64f3b0f4dd2bb3aa1ce8566d220cc74dda9df97d8490cc81d89d735c92e59fb6";
+
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_str(SK).expect("valid secret key");
+        let sk = PrivateKey::new(sk, Network::Bitcoin);
+        let pk = sk.public_key(&secp);
+
+        let redeem_script = ScriptBuf::new_p2wpkh(&CompressedPublicKey(pk.inner).wpubkey_hash());
+        assert_eq!(redeem_script, ScriptBuf::from_hex(REDEEM_SCRIPT).expect("valid hex"));
+
+        let bytes = Vec::<u8>::from_hex(TX).expect("valid hex");
+        let tx: Transaction = consensus::deserialize(&bytes).expect("valid transaction");
+        let utxo = TxOut {
+            value: Amount::from_sat(1_000_000_000),
+            script_pubkey: ScriptBuf::new_p2sh(&redeem_script.script_hash()),
+        };
+        let input = InputBuilder::new(&tx.input[0].previous_output)
+            .witness_utxo(utxo)
+            .redeem_script(redeem_script)
+            .sequence(tx.input[0].sequence)
+            .bip32_derivation(pk.inner, key_source(0))
+            .build()
+            .expect("valid input");
+
+        let mut constructor = Constructor::<Modifiable>::new().input(input);
+        for txout in &tx.output {
+            let output = Output::from_v0(Default::default(), txout.clone()).expect("valid output");
+            constructor = constructor.output(output);
+        }
+        let mut psbt = constructor.into_inner().expect("valid lock time combination");
+        psbt.tx_version = tx.version;
+        psbt.fallback_lock_time = tx.lock_time;
+        assert_eq!(psbt.unsigned_tx().expect("valid lock time combination"), tx);
+
+        let want = <[u8; 32]>::from_hex(SIGHASH).expect("valid hex");
+        let psbt = assert_signs_input(psbt, sk, Message::from_digest(want));
+
+        // The scriptSig of the signed transaction in BIP-143.
+        let script_sig = format!("16{}", REDEEM_SCRIPT);
+        let script_sig = ScriptBuf::from_hex(&script_sig).expect("valid hex");
+        assert_eq!(psbt.inputs[0].final_script_sig, Some(script_sig));
+        assert!(!psbt.inputs[0].is_finalized());
+    }
+
+    #[test]
+    fn sign_p2sh_p2wsh() {
+        let secp = Secp256k1::new();
+        let sk = private_key(1);
+        let pk = sk.public_key(&secp);
+
+        let witness_script = checksig_script(&pk);
+        let redeem_script = ScriptBuf::new_p2wsh(&witness_script.wscript_hash());
+        let utxo = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: ScriptBuf::new_p2sh(&redeem_script.script_hash()),
+        };
+        let input = InputBuilder::new(&OutPoint::COINBASE_PREVOUT)
+            .witness_utxo(utxo.clone())
+            .redeem_script(redeem_script.clone())
+            .witness_script(witness_script.clone())
+            .bip32_derivation(pk.inner, key_source(0))
+            .build()
+            .expect("valid input");
+        let psbt = psbt(input);
+
+        let tx = psbt.unsigned_tx().expect("valid lock time combination");
+        let want = SighashCache::new(&tx)
+            .p2wsh_signature_hash(0, &witness_script, utxo.value, EcdsaSighashType::All)
+            .expect("sighash");
+        let psbt = assert_signs_input(psbt, sk, Message::from_digest(want.to_byte_array()));
+
+        let bytes = <&PushBytes>::try_from(redeem_script.as_bytes()).expect("34 bytes");
+        let script_sig = script::Builder::new().push_slice(bytes).into_script();
+        assert_eq!(psbt.inputs[0].final_script_sig, Some(script_sig));
+    }
+
+    #[test]
+    fn sign_p2sh() {
+        let secp = Secp256k1::new();
+        let sk = private_key(1);
+        let pk = sk.public_key(&secp);
+
+        let redeem_script = checksig_script(&pk);
+        let prev = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::EMPTY_COINBASE],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: ScriptBuf::new_p2sh(&redeem_script.script_hash()),
+            }],
+        };
+        let input = InputBuilder::new(&OutPoint { txid: prev.compute_txid(), vout: 0 })
+            .non_witness_utxo(prev)
+            .redeem_script(redeem_script.clone())
+            .bip32_derivation(pk.inner, key_source(0))
+            .build()
+            .expect("valid input");
+        let psbt = psbt(input);
+
+        let tx = psbt.unsigned_tx().expect("valid lock time combination");
+        let want = SighashCache::new(&tx)
+            .legacy_signature_hash(0, &redeem_script, EcdsaSighashType::All.to_u32())
+            .expect("sighash");
+        let psbt = assert_signs_input(psbt, sk, Message::from_digest(want.to_byte_array()));
+
+        // The scriptSig of a bare P2SH input also contains the signatures, left to the Finalizer.
+        assert!(psbt.inputs[0].final_script_sig.is_none());
+    }
+
+    /// A key store that returns the same key for every request.
+    struct AnyKey(PrivateKey);
+
+    impl GetKey for AnyKey {
+        type Error = GetKeyError;
+
+        fn get_key<C: Signing>(
+            &self,
+            _: KeyRequest,
+            _: &Secp256k1<C>,
+        ) -> Result<Option<PrivateKey>, Self::Error> {
+            Ok(Some(self.0))
+        }
+    }
+
+    /// A key store that fails every request.
+    struct FailingKeys;
+
+    impl GetKey for FailingKeys {
+        type Error = GetKeyError;
+
+        fn get_key<C: Signing>(
+            &self,
+            _: KeyRequest,
+            _: &Secp256k1<C>,
+        ) -> Result<Option<PrivateKey>, Self::Error> {
+            Err(GetKeyError::Bip32(bitcoin::bip32::Error::CannotDeriveFromHardenedKey))
+        }
+    }
+
+    #[test]
+    fn sign_reports_key_store_error() {
+        let secp = Secp256k1::new();
+        let pk = private_key(1).public_key(&secp);
+
+        let utxo = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&CompressedPublicKey(pk.inner).wpubkey_hash()),
+        };
+        let input = InputBuilder::new(&OutPoint::COINBASE_PREVOUT)
+            .witness_utxo(utxo)
+            .bip32_derivation(pk.inner, key_source(0))
+            .build()
+            .expect("valid input");
+
+        let signer = Signer::new(psbt(input)).expect("valid lock time combination");
+        let (used, errors) = signer.sign(&FailingKeys, &secp).unwrap_err();
+        assert!(used.is_empty());
+        assert!(matches!(errors[&0], SignError::GetKey { input_index: 0, .. }));
+    }
+
+    #[test]
+    fn sign_taproot_skips_key_not_matching_origin() {
+        let secp = Secp256k1::new();
+        let (internal_key, _) = private_key(2).public_key(&secp).inner.x_only_public_key();
+
+        let utxo = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: ScriptBuf::new_p2tr(&secp, internal_key, None),
+        };
+        let mut input = InputBuilder::new(&OutPoint::COINBASE_PREVOUT)
+            .witness_utxo(utxo)
+            .build()
+            .expect("valid input");
+        input.tap_internal_key = Some(internal_key);
+        input.tap_key_origins.insert(internal_key, (vec![], key_source(0)));
+
+        let mut signer = Signer::new(psbt(input)).expect("valid lock time combination");
+        let err = signer.sign_taproot(0, &AnyKey(private_key(1)), &secp).unwrap_err();
+        assert_eq!(err, SignError::KeyNotFound { input_index: 0 });
+        assert!(signer.0.inputs[0].tap_key_sig.is_none());
+    }
+}
@@ -1,9 +1,17 @@
 // SPDX-License-Identifier: CC0-1.0
 
-//! The PSBT Version 2 Signer role.
+//! The PSBT Version 2 Updater role.
 
-use crate::error::DetermineLockTimeError;
-use crate::Psbt;
+use core::fmt;
+
+use bitcoin::{Sequence, Txid};
+
+use crate::error::{
+    write_err, DetermineLockTimeError, IndexOutOfBoundsError, OutputsNotModifiableError,
+};
+#[cfg(any(feature = "std", rust_v_1_81))]
+use crate::error::StdError;
+use crate::{Output, Psbt};
 
 /// Implements the BIP-370 Updater role.
 ///
@@ -29,13 +37,63 @@ impl Updater {
     }
 
     /// Updater role, update the sequence number for input at `index`.
+    ///
+    /// The sequence number is committed to by the sighash so if `n` differs from the current
+    /// sequence number all signatures on the input are cleared (see [`crate::Input::clear_signatures`]).
     pub fn set_sequence(
         mut self,
         n: Sequence,
         input_index: usize,
     ) -> Result<Updater, IndexOutOfBoundsError> {
-        let input = self.0.checked_input_mut(input_index)?;
-        input.sequence = Some(n);
+        self.0.set_input_sequence(input_index, n)?;
+        Ok(self)
+    }
+
+    /// Adds an output to the PSBT.
+    ///
+    /// Useful if an output (e.g. change) needs to be added after construction, requires the
+    /// outputs modifiable flag to still be set.
+    pub fn add_output(mut self, output: Output) -> Result<Updater, OutputsNotModifiableError> {
+        if !self.0.is_outputs_modifiable() {
+            return Err(OutputsNotModifiableError);
+        }
+        self.0.outputs.push(output);
+        self.0.output_count += 1;
+        Ok(self)
+    }
+
+    /// Sets the `witness_utxo` of the input at `index` using the output it spends in `parent`.
+    ///
+    /// Useful for chained transactions where the previous transaction is itself a PSBT. The
+    /// input's `previous_txid` is compared to the parent's [`Psbt`] ID (which is computed with
+    /// all sequence numbers zeroed) and the output at `spent_output_index` is attached.
+    ///
+    /// The `non_witness_utxo` is not set because the parent's unsigned transaction is not the
+    /// final transaction that will be seen on the network.
+    ///
+    /// As with [`Self::set_sequence`], if the UTXO changes all signatures on the input are cleared.
+    pub fn set_utxo_from_parent(
+        mut self,
+        index: usize,
+        parent: &Psbt,
+    ) -> Result<Updater, UpdaterError> {
+        let parent_id = parent.id().map_err(UpdaterError::ParentLockTime)?;
+        let input = self.0.checked_input_mut(index)?;
+
+        if input.previous_txid != parent_id {
+            return Err(UpdaterError::NotParent { previous_txid: input.previous_txid, parent_id });
+        }
+        let vout = input.spent_output_index;
+        let utxo = parent
+            .outputs
+            .get(vout as usize)
+            .map(|output| output.tx_out())
+            .ok_or(UpdaterError::MissingOutput { vout })?;
+
+        if input.witness_utxo.as_ref() != Some(&utxo) {
+            input.clear_signatures();
+        }
+        input.witness_utxo = Some(utxo);
         Ok(self)
     }
 
@@ -46,5 +104,61 @@ impl Updater {
 impl TryFrom<Psbt> for Updater {
     type Error = DetermineLockTimeError;
 
-    fn try_from(psbt: Psbt) -> Result<Self, Self::Error> { Self::new(psbt) }
+    fn try_from(psbt: Psbt) -> Result<Self, Self::Error> { Self::from_psbt(psbt) }
+}
+
+/// Error updating a PSBT input from a parent PSBT, see [`Updater::set_utxo_from_parent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UpdaterError {
+    /// The input index is out of bounds.
+    IndexOutOfBounds(IndexOutOfBoundsError),
+    /// Unable to determine the lock time of the parent PSBT, required to calculate its ID.
+    ParentLockTime(DetermineLockTimeError),
+    /// The input does not spend an output of the parent PSBT.
+    NotParent {
+        /// The previous txid of the input.
+        previous_txid: Txid,
+        /// The ID of the parent PSBT.
+        parent_id: Txid,
+    },
+    /// The parent PSBT has no output at the input's spent output index.
+    MissingOutput {
+        /// The spent output index of the input.
+        vout: u32,
+    },
+}
+
+impl fmt::Display for UpdaterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use UpdaterError::*;
+
+        match *self {
+            IndexOutOfBounds(ref e) => write_err!(f, "set utxo from parent"; e),
+            ParentLockTime(ref e) => write_err!(f, "parent PSBT ID"; e),
+            NotParent { ref previous_txid, ref parent_id } => write!(
+                f,
+                "input previous txid {} does not match parent PSBT ID {}",
+                previous_txid, parent_id
+            ),
+            MissingOutput { vout } => write!(f, "parent PSBT has no output at index {}", vout),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for UpdaterError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use UpdaterError::*;
+
+        match *self {
+            IndexOutOfBounds(ref e) => Some(e),
+            ParentLockTime(ref e) => Some(e),
+            NotParent { .. } | MissingOutput { .. } => None,
+        }
+    }
+}
+
+impl From<IndexOutOfBoundsError> for UpdaterError {
+    fn from(e: IndexOutOfBoundsError) -> Self { Self::IndexOutOfBounds(e) }
 }
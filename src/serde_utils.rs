@@ -27,7 +27,7 @@ pub mod btreemap_byte_values {
 
     use bitcoin::hex::FromHex;
 
-    use crate::prelude::*;
+    use crate::internal_prelude::*;
     use crate::serde;
 
     pub fn serialize<S, T>(v: &BTreeMap<T, Vec<u8>>, s: S) -> Result<S::Ok, S::Error>
@@ -95,7 +95,7 @@ pub mod btreemap_as_seq {
 
     // NOTE: This module can be exactly copied to use with HashMap.
 
-    use crate::prelude::*;
+    use crate::internal_prelude::*;
     use crate::serde;
 
     pub fn serialize<S, T, U>(v: &BTreeMap<T, U>, s: S) -> Result<S::Ok, S::Error>
@@ -166,7 +166,7 @@ pub mod btreemap_as_seq_byte_values {
 
     // NOTE: This module can be exactly copied to use with HashMap.
 
-    use crate::prelude::*;
+    use crate::internal_prelude::*;
     use crate::serde;
 
     /// A custom key-value pair type that serialized the bytes as hex.
@@ -303,3 +303,36 @@ pub mod hex_bytes {
         }
     }
 }
+
+pub mod as_psbt_hex {
+    //! Module for serialization of a [`Psbt`] as a hex string of its wire format.
+    //!
+    //! Use with `#[serde(with = "psbt_v2::serde_utils::as_psbt_hex")]`, many APIs expect a PSBT
+    //! as a single string field rather than the structure of the `Psbt` type.
+
+    use bitcoin::hex::FromHex;
+
+    use crate::internal_prelude::*;
+    use crate::serde;
+    use crate::Psbt;
+
+    /// Serializes `psbt` as a hex string.
+    pub fn serialize<S>(psbt: &Psbt, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        s.serialize_str(&psbt.serialize_hex())
+    }
+
+    /// Deserializes a [`Psbt`] from a hex string.
+    pub fn deserialize<'de, D>(d: D) -> Result<Psbt, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        let hex = <String as serde::Deserialize>::deserialize(d)?;
+        let bytes = Vec::<u8>::from_hex(&hex).map_err(D::Error::custom)?;
+        Psbt::deserialize(&bytes).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,500 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Utilities for testing code that uses this crate (requires "test-utils" feature).
+
+use core::ops::Range;
+use core::{fmt, mem};
+
+use bitcoin::sighash::EcdsaSighashType;
+use bitcoin::{absolute, Amount, Sequence};
+
+use crate::error::{write_err, DetermineLockTimeError};
+#[cfg(any(feature = "std", rust_v_1_81))]
+use crate::error::StdError;
+use crate::internal_prelude::{BTreeMap, Box};
+use crate::{CombineError, DeserializeError, Psbt};
+
+/// Converts `psbt` to a serialized version 0 PSBT and back again.
+///
+/// Some information is lost when converting to version 0, before comparing the original PSBT is
+/// normalized as follows:
+///
+/// - `tx_modifiable_flags` is cleared, v0 has no modifiable flags.
+/// - `fallback_lock_time` is set to the determined lock time and each input's `min_time` and
+///   `min_height` fields are cleared, v0 only has the lock time of the unsigned transaction.
+/// - Each input's `sequence` is set to `Sequence::MAX` if not set, since v0 always has one.
+///
+/// # Errors
+///
+/// Returns [`RoundtripError::Mismatch`] if the round tripped PSBT is not equal to the normalized
+/// `psbt`.
+pub fn roundtrip_via_v0(psbt: &Psbt) -> Result<Psbt, RoundtripError> {
+    let lock_time = psbt.determine_lock_time()?;
+    let bytes = psbt.clone().to_psbt_v0()?.serialize();
+    let got = Psbt::deserialize(&bytes)?;
+
+    let mut want = psbt.clone();
+    want.tx_modifiable_flags = 0;
+    want.fallback_lock_time = lock_time;
+    for input in want.inputs.iter_mut() {
+        input.min_time = None;
+        input.min_height = None;
+        input.sequence = Some(input.sequence.unwrap_or(Sequence::MAX));
+    }
+    if got != want {
+        return Err(RoundtripError::Mismatch { got: Box::new(got), want: Box::new(want) });
+    }
+
+    Ok(got)
+}
+
+/// Asserts that combining `a` and `b` gives the same result in either order.
+///
+/// The combined PSBTs are compared using their serialized bytes.
+///
+/// # Panics
+///
+/// If combining in either order fails, or if the results are not equal.
+pub fn assert_combine_commutative(a: &Psbt, b: &Psbt) {
+    let ab = a.clone().combine_with(b.clone()).expect("failed to combine a with b");
+    let ba = b.clone().combine_with(a.clone()).expect("failed to combine b with a");
+
+    assert_eq!(ab.serialize(), ba.serialize(), "combine is not commutative");
+}
+
+/// Splits each fixture into a pair of compatible PSBTs, once for each seed, and asserts that
+/// combining the pair is commutative (see [`assert_combine_commutative`]).
+///
+/// Use a handful of hand built fixtures that set xpubs, signatures, and optional fields and a
+/// large range of seeds to get randomized coverage.
+///
+/// # Panics
+///
+/// If combining any of the pairs is not commutative.
+pub fn assert_combine_commutative_over(fixtures: &[Psbt], seeds: Range<u64>) {
+    for fixture in fixtures {
+        for seed in seeds.clone() {
+            let (a, b) = split_for_combine(fixture, seed);
+            assert_combine_commutative(&a, &b);
+        }
+    }
+}
+
+/// Asserts that combining `a` and `b` fails in either order, with the same kind of error.
+///
+/// The errors are compared by variant, and by input or output index, since the values they
+/// carry depend on the order.
+///
+/// # Panics
+///
+/// If combining in either order succeeds, or if the errors are not of the same kind.
+pub fn assert_combine_conflict(a: &Psbt, b: &Psbt) {
+    let ab = a.clone().combine_with(b.clone()).expect_err("combined conflicting a with b");
+    let ba = b.clone().combine_with(a.clone()).expect_err("combined conflicting b with a");
+
+    assert!(same_kind(&ab, &ba), "combine fails differently: {:?} {:?}", ab, ba);
+}
+
+/// Splits each fixture into a pair of conflicting PSBTs, once for each seed, and asserts that
+/// combining the pair fails in the same way in either order (see [`assert_combine_conflict`]).
+///
+/// # Panics
+///
+/// If combining any of the pairs succeeds or fails differently depending on the order.
+pub fn assert_combine_conflict_over(fixtures: &[Psbt], seeds: Range<u64>) {
+    for fixture in fixtures {
+        for seed in seeds.clone() {
+            let (a, b) = split_with_conflict(fixture, seed);
+            assert_combine_conflict(&a, &b);
+        }
+    }
+}
+
+/// Splits `psbt` as [`split_for_combine`] does then gives one input or output a different value
+/// in each of the PSBTs, using `seed` to pseudo-randomly choose which.
+///
+/// The conflicting field is one of an input's `sequence`, `min_height`, or `sighash_type` (if
+/// the input is not finalized), or an output's `amount`.
+///
+/// # Panics
+///
+/// If `psbt` has no inputs.
+pub fn split_with_conflict(psbt: &Psbt, seed: u64) -> (Psbt, Psbt) {
+    let (mut a, mut b) = split_for_combine(psbt, seed);
+    let mut rng = SplitMix64(!seed);
+
+    let index = (rng.next() % psbt.inputs.len() as u64) as usize;
+    let (x, y) = (&mut a.inputs[index], &mut b.inputs[index]);
+    let kinds = if psbt.outputs.is_empty() { 3 } else { 4 };
+    match rng.next() % kinds {
+        0 => {
+            let sequence = psbt.inputs[index].sequence.unwrap_or(Sequence::MAX);
+            x.sequence = Some(sequence);
+            y.sequence = Some(Sequence::from_consensus(sequence.to_consensus_u32() ^ 1));
+        }
+        1 => {
+            let height = |h| absolute::Height::from_consensus(h).expect("valid height");
+            x.min_height = Some(height(800_000));
+            y.min_height = Some(height(800_001));
+        }
+        2 if !psbt.inputs[index].is_finalized() => {
+            x.sighash_type = Some(EcdsaSighashType::All.into());
+            y.sighash_type = Some(EcdsaSighashType::None.into());
+        }
+        _ if !psbt.outputs.is_empty() => {
+            let index = (rng.next() % psbt.outputs.len() as u64) as usize;
+            b.outputs[index].amount += Amount::from_sat(1);
+        }
+        _ => {
+            x.min_time = Some(absolute::Time::MIN);
+            y.min_time = Some(absolute::Time::MAX);
+        }
+    }
+
+    (a, b)
+}
+
+/// Returns true if `ab` and `ba` are the same kind of error.
+fn same_kind(ab: &CombineError, ba: &CombineError) -> bool {
+    use CombineError::*;
+
+    match (ab, ba) {
+        (Input { index: i, error: e }, Input { index: j, error: f }) =>
+            i == j && mem::discriminant(e) == mem::discriminant(f),
+        (Output { index: i, error: e }, Output { index: j, error: f }) =>
+            i == j && mem::discriminant(e) == mem::discriminant(f),
+        _ => mem::discriminant(ab) == mem::discriminant(ba),
+    }
+}
+
+/// Splits `psbt` into two PSBTs that can be combined, using `seed` to pseudo-randomly choose
+/// which of the two gets each piece of data.
+///
+/// Each xpub, signature, and BIP-32 derivation, and each optional input field (`sequence`, UTXOs,
+/// `sighash_type`, redeem and witness scripts, and `tap_key_sig`) ends up in one or both of the
+/// PSBTs, never in neither. All other data is kept in both.
+pub fn split_for_combine(psbt: &Psbt, seed: u64) -> (Psbt, Psbt) {
+    let mut rng = SplitMix64(seed);
+    let (mut a, mut b) = (psbt.clone(), psbt.clone());
+
+    split_map(&mut rng, &psbt.xpub, &mut a.xpub, &mut b.xpub);
+    for (index, input) in psbt.inputs.iter().enumerate() {
+        let (x, y) = (&mut a.inputs[index], &mut b.inputs[index]);
+
+        split_map(&mut rng, &input.partial_sigs, &mut x.partial_sigs, &mut y.partial_sigs);
+        split_map(&mut rng, &input.tap_script_sigs, &mut x.tap_script_sigs, &mut y.tap_script_sigs);
+        split_map(
+            &mut rng,
+            &input.bip32_derivation,
+            &mut x.bip32_derivation,
+            &mut y.bip32_derivation,
+        );
+
+        split_option(&mut rng, &input.sequence, &mut x.sequence, &mut y.sequence);
+        split_option(&mut rng, &input.witness_utxo, &mut x.witness_utxo, &mut y.witness_utxo);
+        split_option(
+            &mut rng,
+            &input.non_witness_utxo,
+            &mut x.non_witness_utxo,
+            &mut y.non_witness_utxo,
+        );
+        split_option(&mut rng, &input.sighash_type, &mut x.sighash_type, &mut y.sighash_type);
+        split_option(&mut rng, &input.redeem_script, &mut x.redeem_script, &mut y.redeem_script);
+        split_option(
+            &mut rng,
+            &input.witness_script,
+            &mut x.witness_script,
+            &mut y.witness_script,
+        );
+        split_option(&mut rng, &input.tap_key_sig, &mut x.tap_key_sig, &mut y.tap_key_sig);
+    }
+
+    (a, b)
+}
+
+/// Distributes the entries of `from` between `a` and `b`.
+fn split_map<K: Ord + Clone, V: Clone>(
+    rng: &mut SplitMix64,
+    from: &BTreeMap<K, V>,
+    a: &mut BTreeMap<K, V>,
+    b: &mut BTreeMap<K, V>,
+) {
+    a.clear();
+    b.clear();
+    for (key, value) in from {
+        let (in_a, in_b) = rng.pick();
+        if in_a {
+            a.insert(key.clone(), value.clone());
+        }
+        if in_b {
+            b.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Clears the field in `a` or `b` (or neither), both are expected to be copies of `from`.
+fn split_option<T>(rng: &mut SplitMix64, from: &Option<T>, a: &mut Option<T>, b: &mut Option<T>) {
+    if from.is_some() {
+        let (in_a, in_b) = rng.pick();
+        if !in_a {
+            *a = None;
+        }
+        if !in_b {
+            *b = None;
+        }
+    }
+}
+
+/// A small deterministic PRNG (SplitMix64), good enough for distributing test data.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns whether the first and/or second PSBT should get a piece of data, never neither.
+    fn pick(&mut self) -> (bool, bool) {
+        match self.next() % 3 {
+            0 => (true, false),
+            1 => (false, true),
+            _ => (true, true),
+        }
+    }
+}
+
+/// Error round tripping a PSBT via version 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RoundtripError {
+    /// Unable to determine the lock time, required to convert to v0.
+    DetermineLockTime(DetermineLockTimeError),
+    /// Error deserializing the v0 PSBT.
+    Deserialize(DeserializeError),
+    /// The round tripped PSBT is not equal to the normalized original.
+    Mismatch {
+        /// The round tripped PSBT.
+        got: Box<Psbt>,
+        /// The normalized original PSBT.
+        want: Box<Psbt>,
+    },
+}
+
+impl fmt::Display for RoundtripError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use RoundtripError::*;
+
+        match *self {
+            DetermineLockTime(ref e) => write_err!(f, "roundtrip via v0"; e),
+            Deserialize(ref e) => write_err!(f, "roundtrip via v0"; e),
+            Mismatch { .. } => write!(f, "roundtrip via v0: PSBT does not round trip"),
+        }
+    }
+}
+
+#[cfg(any(feature = "std", rust_v_1_81))]
+impl StdError for RoundtripError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        use RoundtripError::*;
+
+        match *self {
+            DetermineLockTime(ref e) => Some(e),
+            Deserialize(ref e) => Some(e),
+            Mismatch { .. } => None,
+        }
+    }
+}
+
+impl From<DetermineLockTimeError> for RoundtripError {
+    fn from(e: DetermineLockTimeError) -> Self { Self::DetermineLockTime(e) }
+}
+
+impl From<DeserializeError> for RoundtripError {
+    fn from(e: DeserializeError) -> Self { Self::Deserialize(e) }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::bip32::{DerivationPath, Fingerprint, KeySource, Xpriv, Xpub};
+    use bitcoin::hashes::Hash as _;
+    use bitcoin::opcodes::all::OP_CHECKMULTISIG;
+    use bitcoin::psbt::PsbtSighashType;
+    use bitcoin::secp256k1::{schnorr, Secp256k1, SecretKey};
+    use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
+    use bitcoin::taproot::TapLeafHash;
+    use bitcoin::{
+        absolute, script, taproot, transaction, Amount, CompressedPublicKey, Network, OutPoint,
+        PrivateKey, ScriptBuf, Transaction, TxIn, TxOut,
+    };
+
+    use super::*;
+    use crate::output::{test_output, Output};
+    use crate::{Constructor, Input, Modifiable, Signer};
+
+    fn private_key(byte: u8) -> PrivateKey {
+        let sk = SecretKey::from_slice(&[byte; 32]).expect("valid secret key");
+        PrivateKey::new(sk, Network::Bitcoin)
+    }
+
+    fn xpub(seed: u8) -> (Xpub, KeySource) {
+        let secp = Secp256k1::new();
+        let xpriv = Xpriv::new_master(Network::Bitcoin, &[seed; 32]).expect("valid seed");
+        let xpub = Xpub::from_priv(&secp, &xpriv);
+        (xpub, (xpub.fingerprint(), DerivationPath::master()))
+    }
+
+    /// A signed PSBT spending a P2WPKH and a 2-of-2 P2WSH input, with global xpubs.
+    fn segwit_v0_fixture() -> Psbt {
+        let secp = Secp256k1::new();
+        let sks = [private_key(1), private_key(2), private_key(3)];
+        let pks = sks.iter().map(|sk| sk.public_key(&secp)).collect::<Vec<_>>();
+        let key_source = (Fingerprint::from([0xd3, 0x4d, 0xb3, 0x3f]), DerivationPath::master());
+
+        let wpkh = CompressedPublicKey(pks[0].inner).wpubkey_hash();
+        let mut p2wpkh = Input::new(&OutPoint { txid: bitcoin::Txid::all_zeros(), vout: 0 });
+        p2wpkh.witness_utxo = Some(TxOut {
+            value: Amount::from_sat(60_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&wpkh),
+        });
+        p2wpkh.sequence = Some(Sequence::ENABLE_RBF_NO_LOCKTIME);
+        p2wpkh.sighash_type = Some(PsbtSighashType::from(EcdsaSighashType::All));
+        p2wpkh.bip32_derivation.insert(pks[0].inner, key_source.clone());
+
+        let witness_script = script::Builder::new()
+            .push_int(2)
+            .push_key(&pks[1])
+            .push_key(&pks[2])
+            .push_int(2)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let mut p2wsh = Input::new(&OutPoint { txid: bitcoin::Txid::all_zeros(), vout: 1 });
+        p2wsh.witness_utxo = Some(TxOut {
+            value: Amount::from_sat(40_000),
+            script_pubkey: ScriptBuf::new_p2wsh(&witness_script.wscript_hash()),
+        });
+        p2wsh.witness_script = Some(witness_script);
+        p2wsh.bip32_derivation.insert(pks[1].inner, key_source.clone());
+        p2wsh.bip32_derivation.insert(pks[2].inner, key_source);
+
+        let mut psbt = Constructor::<Modifiable>::new()
+            .input(p2wpkh)
+            .input(p2wsh)
+            .output(Output { script_pubkey: ScriptBuf::new_p2wpkh(&wpkh), ..test_output(70_000) })
+            .output(test_output(29_000))
+            .into_inner()
+            .expect("valid lock time combination");
+        psbt.extend_xpubs([xpub(0xaa), xpub(0xbb)]).expect("consistent key sources");
+
+        let k = sks.iter().map(|sk| (sk.public_key(&secp), *sk)).collect::<BTreeMap<_, _>>();
+        let signer = Signer::new(psbt).expect("valid lock time combination");
+        let (psbt, _) = signer.sign(&k, &secp).expect("signing succeeds");
+        psbt
+    }
+
+    /// A PSBT spending a legacy P2PKH input and a taproot input with signatures.
+    fn legacy_and_taproot_fixture() -> Psbt {
+        let secp = Secp256k1::new();
+        let pk = private_key(4).public_key(&secp);
+        let (xonly, _) = private_key(5).public_key(&secp).inner.x_only_public_key();
+
+        let prev = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::EMPTY_COINBASE],
+            output: vec![TxOut {
+                value: Amount::from_sat(80_000),
+                script_pubkey: ScriptBuf::new_p2pkh(&pk.pubkey_hash()),
+            }],
+        };
+        let mut legacy = Input::new(&OutPoint { txid: prev.compute_txid(), vout: 0 });
+        legacy.non_witness_utxo = Some(prev);
+        legacy.sequence = Some(Sequence::MAX);
+
+        let signature = |byte| taproot::Signature {
+            signature: schnorr::Signature::from_slice(&[byte; 64]).expect("64 bytes"),
+            sighash_type: TapSighashType::Default,
+        };
+        let leaf_hash = TapLeafHash::all_zeros();
+        let mut p2tr = Input::new(&OutPoint { txid: bitcoin::Txid::all_zeros(), vout: 2 });
+        p2tr.witness_utxo = Some(TxOut {
+            value: Amount::from_sat(20_000),
+            script_pubkey: ScriptBuf::new_p2tr(&secp, xonly, None),
+        });
+        p2tr.tap_internal_key = Some(xonly);
+        p2tr.tap_key_sig = Some(signature(0x01));
+        p2tr.tap_script_sigs.insert((xonly, leaf_hash), signature(0x02));
+
+        let mut psbt = Constructor::<Modifiable>::new()
+            .input(legacy)
+            .input(p2tr)
+            .output(Output {
+                script_pubkey: ScriptBuf::new_p2pkh(&pk.pubkey_hash()),
+                ..test_output(99_000)
+            })
+            .into_inner()
+            .expect("valid lock time combination");
+        psbt.inputs[0].partial_sigs.insert(pk, ecdsa_signature());
+        psbt.extend_xpubs([xpub(0xcc)]).expect("consistent key sources");
+        psbt
+    }
+
+    /// A valid DER encoded ECDSA signature, the signature does not need to verify.
+    fn ecdsa_signature() -> bitcoin::ecdsa::Signature {
+        let secp = Secp256k1::new();
+        let msg = bitcoin::secp256k1::Message::from_digest([0x42; 32]);
+        let signature = secp.sign_ecdsa(&msg, &private_key(4).inner);
+        bitcoin::ecdsa::Signature { signature, sighash_type: EcdsaSighashType::All }
+    }
+
+    fn fixtures() -> Vec<Psbt> { vec![segwit_v0_fixture(), legacy_and_taproot_fixture()] }
+
+    #[test]
+    fn fixtures_have_data_to_split() {
+        let fixtures = fixtures();
+
+        assert_eq!(fixtures[0].xpub.len(), 2);
+        assert_eq!(fixtures[0].inputs[0].partial_sigs.len(), 1);
+        assert_eq!(fixtures[0].inputs[1].partial_sigs.len(), 2);
+        assert!(fixtures[1].inputs[0].non_witness_utxo.is_some());
+        assert!(fixtures[1].inputs[1].tap_key_sig.is_some());
+    }
+
+    #[test]
+    fn combine_is_commutative_over_fixtures() {
+        assert_combine_commutative_over(&fixtures(), 0..256);
+    }
+
+    #[test]
+    fn combining_split_gives_back_fixture() {
+        for fixture in fixtures() {
+            for seed in 0..64 {
+                let (a, b) = split_for_combine(&fixture, seed);
+                let combined = a.combine_with(b).expect("combine split PSBTs");
+                assert_eq!(combined.serialize(), fixture.serialize(), "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip_via_v0_fixtures() {
+        for fixture in fixtures() {
+            let got = roundtrip_via_v0(&fixture).expect("fixture round trips via v0");
+            assert_eq!(got.inputs.len(), fixture.inputs.len());
+        }
+    }
+
+    #[test]
+    fn combine_conflict_fails_in_either_order_over_fixtures() {
+        assert_combine_conflict_over(&fixtures(), 0..256);
+    }
+
+    #[test]
+    fn split_is_deterministic() {
+        let fixture = segwit_v0_fixture();
+        assert_eq!(split_for_combine(&fixture, 7), split_for_combine(&fixture, 7));
+    }
+}